@@ -0,0 +1,142 @@
+
+use std::path::Path;
+
+use toml::{Table, Value};
+use tracing::trace;
+
+use crate::build::Context;
+use crate::config::Config;
+use crate::graph::{self, Graph, Node};
+
+/// GCC options clang doesn't understand or that would write files. `-Werror`
+/// is dropped too, so findings are reported rather than failing the compile.
+const GCC_ONLY: [&str; 4] = [
+	"-save-temps=obj",
+	"-Wbad-function-cast",
+	"-Wduplicated-branches",
+	"-Wduplicated-cond",
+];
+
+/// Value of `analyze.tool`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+	/// `clang --analyze`
+	Clang,
+	/// `clang-tidy`
+	ClangTidy,
+}
+
+/// Static analysis settings (`[analyze]`)
+#[derive(Debug, Clone)]
+pub struct Analyzer {
+	pub tool: Tool,
+	/// Path to the tool
+	pub path: String,
+	/// clang-tidy `-checks=`
+	pub checks: Option<String>,
+	/// Extra arguments passed to the tool
+	pub args: Vec<String>,
+}
+
+impl Analyzer {
+	pub fn from_config(config: &Table) -> Result<Self, String> {
+		let analyze = config.get("analyze");
+		let tool = match analyze.and_then(|a| a.get("tool")) {
+			Some(Value::String(v)) if v == "clang" => Tool::Clang,
+			Some(Value::String(v)) if v == "clang-tidy" => Tool::ClangTidy,
+			Some(v) => return Err(format!("invalid analyze.tool = {v} (expected \"clang\" or \"clang-tidy\")")),
+			None => Tool::Clang,
+		};
+		let path = analyze
+			.and_then(|a| a.get("path"))
+			.and_then(Value::as_str)
+			.unwrap_or(match tool {
+				Tool::Clang => "clang",
+				Tool::ClangTidy => "clang-tidy",
+			})
+			.to_owned();
+		let checks = analyze
+			.and_then(|a| a.get("checks"))
+			.and_then(Value::as_str)
+			.map(str::to_owned);
+		let args: Vec<String> = analyze
+			.and_then(|a| a.get("args"))
+			.and_then(Value::as_array)
+			.map(|args| args.iter()
+				.flat_map(Value::as_str)
+				.map(str::to_owned)
+				.collect())
+			.unwrap_or_default();
+
+		trace!("analyze config");
+		trace!("  tool   = {tool:?}");
+		trace!("  path   = '{path}'");
+		trace!("  checks = {checks:?}");
+		trace!("  args   = [{}]", args.join(","));
+
+		Ok(Self { tool, path, checks, args })
+	}
+
+	/// Program and arguments analyzing `src` with the compile `flags`
+	fn command(&self, ctx: &Context, src: &Path, flags: &[String], cxx: bool) -> (String, Vec<String>) {
+		let toolchain = &ctx.toolchain;
+		let mut clang_flags: Vec<String> = vec![
+			"-D__INTELLISENSE__".into(),
+			"-m32".into(),
+			"-nostdlibinc".into(),
+			"-Wno-gnu-statement-expression".into(),
+			"-Wno-unknown-warning-option".into(),
+		];
+		if cxx {
+			clang_flags.push("-nostdinc++".into());
+		}
+		clang_flags.extend(flags.iter()
+			.filter(|flag| !GCC_ONLY.contains(&flag.as_str()) && !flag.starts_with("-Werror"))
+			.cloned());
+		for dir in ctx.sh_system_include_dirs.iter() {
+			clang_flags.extend(["-isystem".into(), dir.clone()]);
+		}
+		clang_flags.push(format!("--include={}/{}/include/intellisense.h",
+			toolchain.install_root, toolchain.prog_prefix));
+
+		let src = src.display().to_string();
+		let args = match self.tool {
+			Tool::Clang => ["--analyze", "--analyzer-output", "text", "-o", "/dev/null"].into_iter()
+				.map(str::to_owned)
+				.chain(self.args.iter().cloned())
+				.chain(clang_flags)
+				.chain(["-c".into(), src])
+				.collect(),
+			Tool::ClangTidy => self.checks.iter()
+				.map(|checks| format!("-checks={checks}"))
+				.chain(self.args.iter().cloned())
+				.chain([src, "--".into()])
+				.chain(clang_flags)
+				.collect(),
+		};
+		(self.path.clone(), args)
+	}
+}
+
+/// Run the analyzer over every C and C++ source, reporting findings like
+/// compiler diagnostics. Every source is analyzed even when some fail.
+pub fn analyze(config: &Config, reporter: &dyn graph::Reporter) -> std::io::Result<()> {
+	let ctx = Context::new(config, false)?;
+	let analyzer = Analyzer::from_config(&config.table).map_err(std::io::Error::other)?;
+
+	let mut graph = Graph::new();
+	let srcs = ctx.sh_srcs_c.iter()
+		.map(|src| (src, &ctx.sh_cflags, false))
+		.chain(ctx.sh_srcs_cxx.iter().map(|src| (src, &ctx.sh_cxxflags, true)));
+	for (src, flags, cxx) in srcs {
+		let (program, args) = analyzer.command(&ctx, src, flags, cxx);
+		// No outputs, so every source is analyzed each time
+		graph.add(Node::new(src.display().to_string())
+			.input(src)
+			.command(program, args));
+	}
+	let options = graph::Options { keep_going: true, ..graph::Options::default() };
+	graph.run(&graph::Mtime, options, reporter)
+		.map(|_| ())
+		.map_err(std::io::Error::other)
+}
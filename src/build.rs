@@ -0,0 +1,906 @@
+
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use tracing::{trace, warn, error};
+
+use crate::cache::Cache;
+use crate::config::{Config, ReadSize, UpToDate, Warnings};
+use crate::graph::{self, Action, Graph, Node};
+use crate::{database, diagnostic, elf, emulator, size, warnings};
+use crate::libyaul::Libyaul;
+use crate::sslang::Sslang;
+use crate::tool::{self, Invocation, Job, Tool};
+use crate::toolchain::{self, Toolchain, Versions};
+
+/// CD-ROM sector size. The BIOS loads the 1st read file in whole sectors.
+pub const CD_SECTOR_SIZE: u32 = 2048;
+
+/// Paths produced by a build
+#[derive(Debug, Clone)]
+pub struct Outputs {
+	pub build_path: PathBuf,
+	pub program_elf: PathBuf,
+	pub program_bin: PathBuf,
+	pub program_iso: PathBuf,
+	pub program_cue: PathBuf,
+	/// Objects linked into the program
+	pub objects: Vec<PathBuf>,
+}
+
+impl Outputs {
+	pub fn artifacts(&self) -> emulator::Artifacts<'_> {
+		emulator::Artifacts {
+			cue: &self.program_cue,
+			iso: &self.program_iso,
+			bin: &self.program_bin,
+			elf: &self.program_elf,
+		}
+	}
+}
+
+/// Path of the build artifact for `s`, with the absolute path of `s` encoded
+/// into the file name by replacing '/' with '@'
+pub fn convert_build_path<P: AsRef<Path> + Copy>(build_path: P, s: P) -> Result<PathBuf, String> {
+	let s = std::path::absolute(s)
+		.map_err(|_| format!("unable to find path to '{}'", s.as_ref().display()))?
+		.to_str()
+		.ok_or(format!("unable to convert path '{}' to a string", s.as_ref().display()))?
+		.replace('/', "@");
+
+	Ok([ build_path.as_ref(), s.as_ref() ].iter().collect())
+}
+
+/// Turn an `@`-encoded object path from the build directory back into the
+/// path of the source it was built from.
+pub fn revert_build_path(path: &str) -> String {
+	let (file, member) = match path.split_once('(') {
+		Some((file, member)) => (file, Some(member)),
+		None => (path, None),
+	};
+	let name = Path::new(file)
+		.file_name()
+		.and_then(|name| name.to_str())
+		.filter(|name| name.starts_with('@'));
+	let file = match name {
+		Some(name) => name.replace('@', "/"),
+		None => file.to_owned(),
+	};
+	match member {
+		Some(member) => format!("{file}({member}"),
+		None => file,
+	}
+}
+
+/// Every file under `dir`
+fn files_in_dir<P: AsRef<Path>>(dir: P) -> Vec<PathBuf> {
+	let Ok(entries) = std::fs::read_dir(dir.as_ref()) else {
+		return vec![];
+	};
+	entries
+		.flatten()
+		.flat_map(|entry| match entry.file_type() {
+			Ok(kind) if kind.is_dir() => files_in_dir(entry.path()),
+			_ => vec![entry.path()],
+		})
+		.collect()
+}
+
+/// Tools, flags and paths shared by the stages
+pub struct Context<'a> {
+	pub config: &'a Config,
+
+	pub sh_build_path: PathBuf,
+	pub sh_output_path: PathBuf,
+
+	pub sh_cc: String,
+	pub sh_cxx: String,
+	pub sh_ld: String,
+	pub sh_nm: String,
+	pub sh_objcopy: String,
+	pub sh_objdump: String,
+	pub toolchain: Toolchain,
+	/// `toolchain.launcher`, prepended to C, C++ and asm compiles
+	pub launcher: Vec<String>,
+	pub cache: Cache,
+	/// libyaul built from source, linked instead of the installed one
+	pub libyaul: Option<Libyaul>,
+	/// Front ends by precedence, the configured `[tools]` before the built-in
+	/// ones
+	pub tools: Vec<Box<dyn Tool>>,
+
+	pub sh_cflags: Vec<String>,
+	pub sh_cxxflags: Vec<String>,
+	pub sh_ldflags: Vec<String>,
+	/// Include directories GCC searches by default, passed to clang by
+	/// `analyze`
+	pub sh_system_include_dirs: Vec<String>,
+	/// `-specs=` options for every compile and the link, after `-B` for a
+	/// libyaul built from source
+	pub specs: Vec<String>,
+	/// `-specs=` options added to the link of C++ programs
+	pub cpp_specs: Vec<String>,
+
+	/// Builtin assets as (asset file, symbol name, object) triples
+	pub assets: Vec<(PathBuf, String, PathBuf)>,
+	pub sh_srcs_c: Vec<PathBuf>,
+	pub sh_srcs_cxx: Vec<PathBuf>,
+	/// Every source built by a tool, which is all but the assets
+	pub sh_srcs_tools: Vec<PathBuf>,
+	pub sh_objs_uniq: Vec<PathBuf>,
+
+	/// Tool-chain versions of the last build, an input of every compile and
+	/// the link
+	pub build_toolchain: PathBuf,
+	pub build_program_elf: PathBuf,
+	pub build_program_bin: PathBuf,
+	pub build_ip_bin: PathBuf,
+	pub out_program_iso: PathBuf,
+	pub out_program_cue: PathBuf,
+}
+
+impl<'a> Context<'a> {
+	/// The build and output directories are created unless it's a `dry_run`
+	pub fn new(config: &'a Config, dry_run: bool) -> std::io::Result<Self> {
+		let sh_program = config.sh_program.as_str();
+		let mut sh_srcs = config.sh_srcs.clone();
+		let mut sh_symbols = config.sh_symbols.clone();
+
+		let toolchain = Toolchain::discover(&config.table).map_err(std::io::Error::other)?;
+
+		let sh_build_path = config.build_path();
+		let sh_output_path = config.output_path();
+
+		let libyaul = Libyaul::from_config(&config.table, &sh_build_path).map_err(std::io::Error::other)?;
+		let yaul_cflags_shared = match &libyaul {
+			Some(libyaul) => format!("-I{}", libyaul.include_dir(&toolchain).display()),
+			None => format!("-I{}", toolchain.include_dir()),
+		};
+
+		let yaul_cflags = yaul_cflags_shared.clone();
+		let yaul_cxxflags = yaul_cflags_shared.clone();
+
+		sh_symbols.extend([
+			format!("-Wl,--defsym=___master_stack=0x{:x}", config.ip_main_stack_addr),
+			format!("-Wl,--defsym=___slave_stack=0x{:x}", config.ip_sub_stack_addr),
+		]);
+
+		let sh_cflags_shared = vec![
+			"-W".to_string(),
+			"-Wall".to_string(),
+			"-Wduplicated-branches".to_string(),
+			"-Wduplicated-cond".to_string(),
+			"-Wextra".to_string(),
+			"-Winit-self".to_string(),
+			"-Wmissing-include-dirs".to_string(),
+			"-Wno-format".to_string(),
+			"-Wno-main".to_string(),
+			"-Wnull-dereference".to_string(),
+			"-Wshadow".to_string(),
+			"-Wstrict-aliasing".to_string(),
+			"-Wunused".to_string(),
+			"-Wunused-parameter".to_string(),
+			"-save-temps=obj".to_string(),
+		];
+
+		let mut sh_ldflags = vec![
+			"-static".to_string(),
+			"-Wl,--gc-sections".to_string(),
+			format!("-Wl,-Map,{}/{sh_program}.map", sh_build_path.display()),
+		];
+
+		let werror = (config.warnings == Warnings::Deny).then(|| "-Werror".to_string());
+
+		let sh_cflags: Vec<String> = vec![
+			"-std=c11",
+			"-Wbad-function-cast",
+		].into_iter()
+			.map(|s| s.to_string())
+			.chain(sh_cflags_shared.iter().cloned())
+			.chain(std::iter::once(yaul_cflags))
+			.chain(werror.clone())
+			.collect();
+
+		let sh_cxxflags: Vec<String> = vec![
+			"-std=c++17",
+			"-fno-exceptions",
+			"-fno-rtti",
+			"-fno-unwind-tables",
+			"-fno-asynchronous-unwind-tables",
+			"-fno-threadsafe-statics",
+			"-fno-use-cxa-atexit",
+		].into_iter()
+			.map(|s| s.to_string())
+			.chain(sh_cflags_shared.iter().cloned())
+			.chain(std::iter::once(yaul_cxxflags))
+			.chain(werror)
+			.collect();
+
+		if !dry_run {
+			std::fs::create_dir_all(&config.dir_build)?;
+			std::fs::create_dir_all(&config.dir_output)?;
+		}
+
+		trace!("builtin assets");
+		let mut assets = vec![];
+		for (file, name) in config.assets.iter() {
+			trace!("  {}/{file} -> {name}", config.dir_asset.display());
+
+			let asset_path = PathBuf::from(file.clone() + ".o");
+			match convert_build_path(&sh_build_path, &asset_path) {
+				Ok(target) => assets.push((config.dir_asset.join(file), name.clone(), target)),
+				Err(e) => {
+					error!("{e}");
+					continue;
+				}
+			}
+
+			sh_srcs.push(asset_path);
+		}
+
+		let sh_srcs_uniq = {
+			let mut temp = sh_srcs.clone();
+			temp.sort_unstable();
+			temp.dedup();
+			temp
+		};
+
+		let mut tools = tool::from_config(&config.table).map_err(std::io::Error::other)?;
+		tools.extend([
+			Box::new(tool::Cc) as Box<dyn Tool>,
+			Box::new(tool::Cxx),
+			Box::new(tool::Asm),
+			Box::new(Sslang::from_config(&config.table).map_err(std::io::Error::other)?),
+		]);
+
+		let mut sh_srcs_tools = vec![];
+		let mut unbuilt = vec![];
+		for file in sh_srcs_uniq.iter() {
+			match tool_for(&tools, file) {
+				Some(_) => sh_srcs_tools.push(file.clone()),
+				// Assets are built by their own stage
+				None if config.assets.iter().any(|(asset, _)| *file == Path::new(&format!("{asset}.o"))) => {}
+				None => {
+					warn!("no tool builds '{}', add one to [tools] in config.toml", file.display());
+					unbuilt.push(file.clone());
+				}
+			}
+		}
+		// By extension, so sources built by a [tools] entry in place of GCC are
+		// still C or C++
+		let srcs_of = |language: &dyn Tool| -> Vec<PathBuf> {
+			let extensions = language.extensions();
+			sh_srcs_tools.iter()
+				.filter(|file| file.extension()
+					.and_then(|ext| ext.to_str())
+					.is_some_and(|ext| extensions.iter().any(|x| x == ext)))
+				.cloned()
+				.collect()
+		};
+		let sh_srcs_c = srcs_of(&tool::Cc);
+		let sh_srcs_cxx = srcs_of(&tool::Cxx);
+
+		trace!("generating unique SH objects list");
+		let mut sh_objs_uniq = Vec::<PathBuf>::new();
+		for file in sh_srcs_uniq.iter().filter(|file| !unbuilt.contains(file)) {
+			match convert_build_path(&sh_build_path, file) {
+				Ok(path) => {
+					trace!("  {}", path.with_extension("o").display());
+					sh_objs_uniq.push(path.with_extension("o"));
+				}
+				Err(e) => error!("{e}"),
+			}
+		}
+
+		sh_ldflags.extend(sh_symbols);
+
+		let sh_specs = ["yaul.specs", "yaul-main.specs"];
+
+		// If there are any C++ files, add the specific C++ specs file. This is done
+		// to avoid adding (small) bloat to any C-only projects.
+		let sh_cxx_specs = if !sh_srcs_cxx.is_empty() {
+			vec!["yaul-main-c++.specs"]
+		} else {
+			vec![]
+		};
+
+		let sh_cc = toolchain.program("gcc");
+
+		// Parse out included paths from GCC when the specs files are used. This is used
+		// to explicitly populate each command database entry with include paths
+		let sh_system_include_dirs: Vec<String> = cmd!(&sh_cc, "-E", "-Wp,-v", "-")
+			.stdin_bytes(Vec::new())
+			.stdout_null()
+			.stderr_to_stdout()
+			.read()
+			.map_err(|e| std::io::Error::new(e.kind(), format!("unable to find the include directories of '{sh_cc}': {e}")))?
+			.lines()
+			.filter(|line| line.starts_with(char::is_whitespace))
+			.map(|dir| dir.trim().replace('\\', "/"))
+			.collect();
+		trace!("SH system include directories");
+		for dir in sh_system_include_dirs.iter() {
+			trace!("  {dir}");
+		}
+
+		// GCC looks for specs files in '-B' directories before its own
+		let specs: Vec<String> = libyaul.iter()
+			.map(|libyaul| format!("-B{}/", libyaul.lib_dir(&toolchain).display()))
+			.chain(sh_specs.iter().map(|spec| format!("-specs={spec}")))
+			.collect();
+		let cpp_specs: Vec<String> = sh_cxx_specs.iter()
+			.map(|spec| format!("-specs={spec}"))
+			.collect();
+
+		let build_program_bin = sh_build_path.join(format!("{sh_program}.bin"));
+
+		Ok(Self {
+			config,
+			sh_cxx: toolchain.program("g++"),
+			sh_ld: toolchain.program("gcc"),
+			sh_nm: toolchain.program("gcc-nm"),
+			sh_objcopy: toolchain.program("objcopy"),
+			sh_objdump: toolchain.program("objdump"),
+			sh_cc,
+			cache: Cache::from_config(&config.table).map_err(std::io::Error::other)?,
+			launcher: toolchain::launcher(&config.table).map_err(std::io::Error::other)?,
+			libyaul,
+			tools,
+			sh_system_include_dirs,
+			toolchain,
+			sh_cflags,
+			sh_cxxflags,
+			sh_ldflags,
+			specs,
+			cpp_specs,
+			assets,
+			sh_srcs_c,
+			sh_srcs_cxx,
+			sh_srcs_tools,
+			sh_objs_uniq,
+			build_toolchain: Versions::path(&sh_build_path),
+			build_program_elf: build_program_bin.with_extension("elf"),
+			build_ip_bin: sh_build_path.join("IP.BIN"),
+			out_program_iso: sh_output_path.join(format!("{sh_program}.iso")),
+			out_program_cue: sh_output_path.join(format!("{sh_program}.cue")),
+			build_program_bin,
+			sh_build_path,
+			sh_output_path,
+		})
+	}
+
+	pub fn build_c_options(&self, src: &Path, target: &Path) -> Vec<String> {
+		[
+			"-MT".into(), target.display().to_string(),
+			"-MF".into(), target.with_extension("d").display().to_string(),
+			"-MD".into(),
+		].into_iter()
+			.chain(self.sh_cflags.clone())
+			.chain(self.specs.clone())
+			.chain([
+				"-c".into(),
+				"-o".into(),
+				target.display().to_string(),
+				src.display().to_string(),
+			])
+			.collect()
+	}
+
+	pub fn build_cxx_options(&self, src: &Path, target: &Path) -> Vec<String> {
+		[
+			"-MT".into(), target.display().to_string(),
+			"-MF".into(), target.with_extension("d").display().to_string(),
+			"-MD".into(),
+		].into_iter()
+			.chain(self.sh_cxxflags.clone())
+			.chain(self.specs.clone())
+			.chain([
+				"-c".into(),
+				"-o".into(),
+				target.display().to_string(),
+				src.display().to_string(),
+			])
+			.collect()
+	}
+
+	pub fn build_asm_options(&self, src: &Path, target: &Path) -> Vec<String> {
+		self.sh_cflags.clone()
+			.into_iter()
+			.chain([
+				"-c".into(),
+				"-o".into(),
+				target.display().to_string(),
+				src.display().to_string(),
+			])
+			.collect()
+	}
+
+	/// Program and arguments compiling with `compiler`, run through the
+	/// launcher if there is one
+	pub fn compile_command(&self, compiler: &str, options: Vec<String>) -> (String, Vec<String>) {
+		match self.launcher.split_first() {
+			Some((launcher, args)) => (launcher.clone(), args.iter()
+				.cloned()
+				.chain(std::iter::once(compiler.to_owned()))
+				.chain(options)
+				.collect()),
+			None => (compiler.to_owned(), options),
+		}
+	}
+
+	pub fn build_elf_options(&self) -> Vec<String> {
+		self.specs.clone()
+			.into_iter()
+			.chain(self.cpp_specs.clone())
+			.chain(self.sh_objs_uniq.iter().map(|obj| format!("{}", obj.display())))
+			.chain(self.sh_ldflags.clone())
+			.chain([
+				"-o".into(),
+				self.build_program_elf.display().to_string(),
+			])
+			.collect()
+	}
+
+	/// The tool building `path`, by its extension
+	pub fn tool(&self, path: &Path) -> Option<&dyn Tool> {
+		tool_for(&self.tools, path)
+	}
+
+	/// Tool runs building every source's object. A tool writing something
+	/// other than the object hands it on to the tool for its extension.
+	pub fn jobs(&self) -> Vec<Job<'_>> {
+		let mut jobs = vec![];
+		for src in self.sh_srcs_tools.iter() {
+			let object = match convert_build_path(&self.sh_build_path, &src.with_extension("o")) {
+				Ok(object) => object,
+				Err(e) => {
+					error!("{e}");
+					continue;
+				}
+			};
+			let mut src = src.clone();
+			// Bounded, in case tools hand sources back and forth
+			for _ in 0..self.tools.len() {
+				let Some(tool) = self.tool(&src) else {
+					error!("no tool builds '{}', add one to [tools] in config.toml", src.display());
+					break;
+				};
+				let output = tool.output(&src, &object);
+				let done = output == object;
+				jobs.push(Job { tool, src, output: output.clone(), object: object.clone() });
+				if done {
+					break;
+				}
+				src = output;
+			}
+		}
+		jobs
+	}
+
+	/// `libyaul.a` when it's built from source, an input of every compile and
+	/// the link
+	pub fn libyaul_library(&self) -> Option<PathBuf> {
+		self.libyaul.as_ref().map(|libyaul| libyaul.library(&self.toolchain))
+	}
+
+	pub fn outputs(&self) -> Outputs {
+		Outputs {
+			build_path: self.sh_build_path.clone(),
+			program_elf: self.build_program_elf.clone(),
+			program_bin: self.build_program_bin.clone(),
+			program_iso: self.out_program_iso.clone(),
+			program_cue: self.out_program_cue.clone(),
+			objects: self.sh_objs_uniq.clone(),
+		}
+	}
+}
+
+/// Something that adds nodes to the build graph. Stages are plain functions
+/// of the shared context, so new ones (asset converters, other languages,
+/// the M68k side) can be added to [`STAGES`] without touching the others.
+pub trait Stage {
+	fn nodes<'a>(&self, ctx: &'a Context) -> Vec<Node<'a>>;
+}
+
+impl<F> Stage for F
+where
+	F: for<'a> Fn(&'a Context) -> Vec<Node<'a>>,
+{
+	fn nodes<'a>(&self, ctx: &'a Context) -> Vec<Node<'a>> {
+		self(ctx)
+	}
+}
+
+/// The pipeline, in the order nodes are added to the graph
+pub const STAGES: &[&dyn Stage] = &[
+	&libyaul_nodes,
+	&asset_nodes,
+	&tool_nodes,
+	&elf_nodes,
+	&bin_nodes,
+	&ip_nodes,
+	&iso_nodes,
+	&cue_nodes,
+];
+
+/// The tool whose extensions include the extension of `path`
+fn tool_for<'t>(tools: &'t [Box<dyn Tool>], path: &Path) -> Option<&'t dyn Tool> {
+	let ext = path.extension()?.to_str()?;
+	tools.iter()
+		.find(|tool| tool.extensions().iter().any(|x| x == ext))
+		.map(Box::as_ref)
+}
+
+/// Build graph for the whole pipeline
+pub fn graph<'a>(ctx: &'a Context) -> Graph<'a> {
+	let mut graph = Graph::new();
+	for stage in STAGES {
+		for node in stage.nodes(ctx) {
+			graph.add(node);
+		}
+	}
+	graph
+}
+
+/// Run every stale node of the pipeline (libyaul -> assets -> tools -> ELF ->
+/// BIN -> IP.BIN -> ISO -> CUE)
+pub fn build(config: &Config, options: graph::Options, reporter: &dyn graph::Reporter) -> std::io::Result<Outputs> {
+	let ctx = Context::new(config, options.dry_run)?;
+
+	// Checked before anything runs, and recorded so a different tool-chain
+	// rebuilds everything
+	let versions = ctx.toolchain.versions().map_err(std::io::Error::other)?;
+	versions.check(&config.table).map_err(std::io::Error::other)?;
+	if !options.dry_run {
+		versions.record(&ctx.build_toolchain).map_err(std::io::Error::other)?;
+	}
+
+	let graph = graph(&ctx);
+	let reporter = &diagnostic::RevertPaths { build_path: ctx.sh_build_path.clone(), inner: reporter };
+	match config.up_to_date {
+		UpToDate::Mtime => graph.run(&graph::Mtime, options, reporter),
+		UpToDate::Hash => {
+			// Record what did get built, even if a later node failed
+			let policy = database::Hash::load(&ctx.sh_build_path);
+			let result = graph.run(&policy, options, reporter);
+			if options.dry_run {
+				result
+			} else {
+				let saved = policy.save();
+				result.and_then(|ran| saved.map(|_| ran))
+			}
+		}
+	}.map_err(std::io::Error::other)?;
+	if config.warnings == Warnings::Baseline && !options.dry_run {
+		let current = warnings::collect(&ctx.sh_objs_uniq);
+		warnings::check_baseline(&config.warnings_baseline, current)
+			.map_err(std::io::Error::other)?;
+	}
+	if ctx.cache.enabled && !options.dry_run {
+		ctx.cache.trim();
+	}
+	Ok(ctx.outputs())
+}
+
+/// Build and install libyaul from source into the build directory whenever a
+/// file of the source tree changes
+pub fn libyaul_nodes<'a>(ctx: &'a Context) -> Vec<Node<'a>> {
+	let Some(libyaul) = &ctx.libyaul else {
+		return vec![];
+	};
+	vec![Node::new("libyaul")
+		.inputs(libyaul.sources())
+		.output(libyaul.library(&ctx.toolchain))
+		.call_with_output(format!("make -C {} {}", libyaul.source.display(), libyaul.targets().join(" ")),
+			move |output| libyaul.make(&ctx.toolchain, output))
+		.shell(libyaul.make_shell(&ctx.toolchain))]
+}
+
+pub fn asset_nodes<'a>(ctx: &'a Context) -> Vec<Node<'a>> {
+	ctx.assets.iter()
+		.map(|(file, name, target)| Node::new(file.display().to_string())
+			.input(file)
+			.output(target)
+			.action(Action::Command {
+				program: ctx.toolchain.tool("bin2o"),
+				args: vec![
+					file.display().to_string(),
+					name.clone(),
+					target.display().to_string(),
+				],
+				stdout: None,
+				quiet: true,
+			}))
+		.collect()
+}
+
+/// One node per tool run. GCC compiles go through the object cache when
+/// it's enabled, and the warnings of programs writing objects are recorded
+/// for the baseline.
+pub fn tool_nodes<'a>(ctx: &'a Context) -> Vec<Node<'a>> {
+	let mut nodes = vec![];
+	for job in ctx.jobs() {
+		let Job { tool, src, output, object } = job;
+		let depfile = tool.depfile(&output);
+		let mut node = Node::new(src.display().to_string())
+			.input(&src)
+			.inputs(tool.inputs(ctx))
+			.output(&output);
+
+		let invocation = tool.invocation(ctx, &src, &output);
+		let cached = tool.cacheable() && ctx.cache.enabled;
+		let baseline = ctx.config.warnings == Warnings::Baseline
+			&& output == object
+			&& matches!(invocation, Invocation::Command { .. });
+		match invocation {
+			Invocation::Command { program, args } if cached || baseline => {
+				// Run from Rust so the cache and the baseline see the compile
+				let command = Action::Command {
+					program: program.clone(),
+					args: args.clone(),
+					stdout: None,
+					quiet: false,
+				};
+				let (description, shell) = (command.describe(), command.shell());
+				let target = output.clone();
+				node = node.call_with_output(description, move |output| {
+					let start = output.len();
+					if cached {
+						ctx.cache.compile(&program, &args, &src, &target, output)?;
+					} else {
+						graph::run_captured(cmd(&program, &args), &program, output)
+							.map_err(|e| e.message)?;
+					}
+					if baseline {
+						warnings::record(&target, &output[start..])?;
+					}
+					Ok(())
+				});
+				if let Some(shell) = shell {
+					node = node.shell(shell);
+				}
+			}
+			invocation => node = node.action(invocation.into_action(&src, &output)),
+		}
+		if let Some(depfile) = depfile {
+			node = node.depfile(depfile);
+		}
+		if baseline {
+			node = node.output(warnings::record_path(&object));
+		}
+		nodes.push(node);
+	}
+	nodes
+}
+
+/// Link, symbol/assembly dumps, memory budget check and size report
+pub fn elf_nodes<'a>(ctx: &'a Context) -> Vec<Node<'a>> {
+	let config = ctx.config;
+	let elf = &ctx.build_program_elf;
+	let (size_report, size_report_prev) = size::report_paths(&ctx.sh_build_path, &config.sh_program);
+
+	let link = Node::new(format!("{}.elf", config.sh_program))
+		.inputs(ctx.sh_objs_uniq.iter().cloned())
+		.input(&ctx.build_toolchain)
+		.inputs(ctx.libyaul_library())
+		.output(elf)
+		.output(elf.with_extension("map"))
+		.output(elf.with_extension("sym"))
+		.output(elf.with_extension("asm"))
+		.command(&ctx.sh_ld, ctx.build_elf_options())
+		.action(Action::Command {
+			program: ctx.sh_nm.clone(),
+			args: vec!["-S".into(), elf.display().to_string()],
+			stdout: Some(elf.with_extension("sym")),
+			quiet: false,
+		})
+		.action(Action::Command {
+			program: ctx.sh_objdump.clone(),
+			args: vec!["-S".into(), elf.display().to_string()],
+			stdout: Some(elf.with_extension("asm")),
+			quiet: false,
+		});
+
+	// The region table is kept as a stamp, so the check only reruns when the
+	// program or the budget changes
+	let memory_stamp = elf.with_extension("memory");
+	let memory = Node::new("memory budget")
+		.input(elf)
+		.input(&config.path)
+		.output(&memory_stamp)
+		.call_with_output("check memory budget", move |output| {
+			let sections = elf::read_sections(elf)?;
+			let start = output.len();
+			config.memory_budget.check(&sections, &[
+				("ip.main-stack-addr", config.ip_main_stack_addr),
+				("ip.sub-stack-addr", config.ip_sub_stack_addr),
+			], output)?;
+			std::fs::write(&memory_stamp, &output[start..])
+				.map_err(|e| format!("unable to write '{}': {e}", memory_stamp.display()))
+		});
+
+	let report = Node::new("size report")
+		.input(elf)
+		.input(elf.with_extension("map"))
+		.input(elf.with_extension("sym"))
+		.output(&size_report)
+		.call("generate size report", move || {
+			let sections = elf::read_sections(elf)?;
+			let report = size::Report::generate(&sections,
+				elf.with_extension("map"),
+				elf.with_extension("sym"),
+			)?;
+			if size_report.exists() {
+				std::fs::rename(&size_report, &size_report_prev)
+					.map_err(|e| format!("unable to rotate '{}': {e}", size_report.display()))?;
+			}
+			report.save(&size_report)
+		});
+
+	vec![link, memory, report]
+}
+
+pub fn bin_nodes<'a>(ctx: &'a Context) -> Vec<Node<'a>> {
+	let bin = &ctx.build_program_bin;
+	vec![Node::new(format!("{}.bin", ctx.config.sh_program))
+		.input(&ctx.build_program_elf)
+		.output(bin)
+		.command(&ctx.sh_objcopy, vec![
+			"-O".into(), "binary".into(),
+			ctx.build_program_elf.display().to_string(),
+			bin.display().to_string(),
+		])
+		.call_with_output(format!("du -hs {}", bin.display()), move |output| {
+			let expr = cmd!("du", "-hs", bin.display().to_string())
+				.pipe(cmd!("awk", r#"{ print $1; }"#));
+			graph::run_captured(expr, "du", output)
+				.map_err(|e| e.message)
+		})]
+}
+
+pub fn ip_nodes<'a>(ctx: &'a Context) -> Vec<Node<'a>> {
+	let config = ctx.config;
+	let sh_program = &config.sh_program;
+	let bin = &ctx.build_program_bin;
+	let yaul_ip_sx = ctx.toolchain.share("yaul/ip/ip.sx");
+	let make_ip = ctx.toolchain.tool("make-ip");
+
+	// Without the checks, and with 'auto' rounded up to whole sectors by the
+	// shell
+	let read_size = match config.ip_1st_read_size {
+		ReadSize::Auto => format!("$(printf 0x%x $(( ($(wc -c < {bin}) + {n} - 1) / {n} * {n} )))",
+			bin = graph::quote(&bin.display().to_string()), n = CD_SECTOR_SIZE),
+		ReadSize::Fixed(size) => format!("0x{size:0x}"),
+	};
+	let shell = format!("{} {read_size}", graph::shell_command(&make_ip, &[
+		bin.display().to_string(),
+		config.ip_version.clone(),
+		config.ip_release_date.to_string(),
+		config.ip_areas.clone(),
+		config.ip_peripherals.clone(),
+		format!("'{}'", config.ip_title),
+		format!("0x{:0x}", config.ip_main_stack_addr),
+		format!("0x{:0x}", config.ip_sub_stack_addr),
+		format!("0x{:0x}", config.ip_1st_read_addr),
+	]));
+
+	vec![Node::new("IP.BIN")
+		.input(yaul_ip_sx)
+		.input(bin)
+		.input(&config.path)
+		.output(&ctx.build_ip_bin)
+		.call_with_output("make-ip", move |output| {
+			let program_size = std::fs::metadata(bin)
+				.map_err(|e| format!("unable to read '{}': {e}", bin.display()))?
+				.len();
+			let too_big = || format!("{sh_program}.bin (0x{program_size:x} bytes) doesn't fit in the 1st read range");
+			let program_size = u32::try_from(program_size).map_err(|_| too_big())?;
+			let ip_1st_read_size = match config.ip_1st_read_size {
+				ReadSize::Auto => program_size.div_ceil(CD_SECTOR_SIZE)
+					.checked_mul(CD_SECTOR_SIZE)
+					.ok_or_else(too_big)?,
+				ReadSize::Fixed(size) => {
+					if size != 0 && size < program_size {
+						return Err(format!("ip.1st-read-size (0x{size:x}) is smaller than {sh_program}.bin (0x{program_size:x})"));
+					}
+					size
+				}
+			};
+			trace!("1st read size: 0x{ip_1st_read_size:x} ({sh_program}.bin is 0x{program_size:x})");
+
+			// The stacks grow downwards, so a stack address inside the loaded range
+			// means the stack will overwrite the end of the program.
+			let ip_1st_read_addr = config.ip_1st_read_addr;
+			let read_end = ip_1st_read_addr.checked_add(ip_1st_read_size.max(program_size))
+				.ok_or_else(|| format!("the 1st read range (0x{ip_1st_read_addr:08x} + 0x{:x}) is past the end of memory",
+					ip_1st_read_size.max(program_size)))?;
+			for (property, stack_addr) in [
+				("ip.main-stack-addr", config.ip_main_stack_addr),
+				("ip.sub-stack-addr", config.ip_sub_stack_addr),
+			] {
+				if ip_1st_read_addr < stack_addr && stack_addr <= read_end {
+					warn!("{property} (0x{stack_addr:08x}) overlaps the 1st read range (0x{ip_1st_read_addr:08x}..0x{read_end:08x})");
+				}
+			}
+
+			let expr = cmd!(&make_ip,
+				bin.display().to_string(),
+				&config.ip_version,
+				config.ip_release_date.to_string(),
+				&config.ip_areas,
+				&config.ip_peripherals,
+				format!("'{}'", config.ip_title),
+				format!("0x{:0x}", config.ip_main_stack_addr),
+				format!("0x{:0x}", config.ip_sub_stack_addr),
+				format!("0x{ip_1st_read_addr:0x}"),
+				format!("0x{ip_1st_read_size:0x}"),
+			);
+			graph::run_captured(expr, "make-ip", output)
+				.map_err(|e| e.message)
+		})
+		.shell(shell)]
+}
+
+pub fn iso_nodes<'a>(ctx: &'a Context) -> Vec<Node<'a>> {
+	let dir_image = &ctx.config.dir_image;
+
+	// TODO - srenshaw - Do we need this to be configurable?
+	let image_1st_read_bin    = "A.BIN"; // ISO/CUE
+	let image_txts = ["ABS.TXT", "BIB.TXT", "CPY.TXT"];
+
+	// The node writes these into the image directory itself
+	let written: Vec<PathBuf> = std::iter::once(image_1st_read_bin)
+		.chain(image_txts)
+		.map(|file| dir_image.join(file))
+		.collect();
+	let image_files: Vec<PathBuf> = files_in_dir(dir_image).into_iter()
+		.filter(|file| !written.contains(file))
+		.collect();
+
+	vec![Node::new(format!("{}.iso", ctx.config.sh_program))
+		.input(&ctx.build_ip_bin)
+		.input(&ctx.build_program_bin)
+		.inputs(image_files)
+		.output(&ctx.out_program_iso)
+		.command("mkdir", vec!["-p".into(), dir_image.display().to_string()])
+		.command("cp", vec![
+			ctx.build_program_bin.display().to_string(),
+			format!("{}/{image_1st_read_bin}", dir_image.display()),
+		])
+		.call("create empty ABS.TXT, BIB.TXT and CPY.TXT", move || {
+			for txt in image_txts {
+				let path = dir_image.join(txt);
+				match std::fs::exists(&path) {
+					Ok(false) => std::fs::write(&path, "empty")
+						.map_err(|e| format!("unable to write '{}': {e}", path.display()))?,
+					Ok(true) => {}
+					Err(e) => error!("{e}"),
+				}
+			}
+			Ok(())
+		})
+		.shell(image_txts.map(|txt| {
+			let txt = graph::quote(&dir_image.join(txt).display().to_string());
+			format!("([ -e {txt} ] || printf empty > {txt})")
+		}).join(" && "))
+		.command(ctx.toolchain.tool("make-iso"), vec![
+			dir_image.display().to_string(),
+			ctx.build_ip_bin.display().to_string(),
+			ctx.sh_output_path.display().to_string(),
+			ctx.config.sh_program.clone(),
+		])]
+}
+
+pub fn cue_nodes<'a>(ctx: &'a Context) -> Vec<Node<'a>> {
+	let dir_audio = &ctx.config.dir_audio;
+
+	vec![Node::new(format!("{}.cue", ctx.config.sh_program))
+		.input(&ctx.out_program_iso)
+		.inputs(files_in_dir(dir_audio))
+		.output(&ctx.out_program_cue)
+		.command("mkdir", vec!["-p".into(), dir_audio.display().to_string()])
+		.command(ctx.toolchain.tool("make-cue"), vec![
+			dir_audio.display().to_string(),
+			ctx.out_program_iso.display().to_string(),
+		])]
+}
@@ -0,0 +1,458 @@
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use duct::cmd;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml::{Table, Value};
+use tracing::{trace, debug, warn};
+
+use crate::graph;
+
+/// Stands for the project root in cached diagnostics
+const ROOT: &str = "{root}/";
+
+/// Fraction of `max-size` the cache is trimmed down to once it's full
+const TRIM_RATIO: f64 = 0.9;
+
+/// Local object cache shared between checkouts (`[cache]`)
+#[derive(Debug, Clone)]
+pub struct Cache {
+	pub enabled: bool,
+	pub dir: PathBuf,
+	/// Bytes the cache may use before the least recently used objects are
+	/// evicted
+	pub max_size: u64,
+}
+
+/// A cached object and the files kept with it
+struct Entry {
+	last_use: SystemTime,
+	size: u64,
+	files: Vec<PathBuf>,
+}
+
+/// Hit/miss counters kept in the cache directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Stats {
+	pub hits: u64,
+	pub misses: u64,
+}
+
+impl Cache {
+	pub fn from_config(config: &Table) -> Result<Self, String> {
+		let cache = config.get("cache");
+		let enabled = cache
+			.and_then(|c| c.get("enabled"))
+			.and_then(Value::as_bool)
+			.unwrap_or(false);
+		let dir = cache
+			.and_then(|c| c.get("dir"))
+			.and_then(Value::as_str)
+			.map(PathBuf::from)
+			.unwrap_or_else(default_dir);
+		let max_size = match cache.and_then(|c| c.get("max-size")) {
+			Some(Value::Integer(v)) => *v as u64,
+			Some(Value::String(v)) => parse_size(v)
+				.ok_or_else(|| format!("invalid cache.max-size = \"{v}\" (expected e.g. \"512M\" or \"2G\")"))?,
+			Some(v) => return Err(format!("invalid cache.max-size = {v} (expected bytes or a size string)")),
+			None => 2 << 30,
+		};
+
+		trace!("cache config");
+		trace!("  enabled  = {enabled}");
+		trace!("  dir      = '{}'", dir.display());
+		trace!("  max-size = {max_size}");
+
+		Ok(Self { enabled, dir, max_size })
+	}
+
+	fn stats_path(&self) -> PathBuf {
+		self.dir.join("stats.json")
+	}
+
+	pub fn stats(&self) -> Stats {
+		std::fs::read_to_string(self.stats_path())
+			.ok()
+			.and_then(|data| serde_json::from_str(&data).ok())
+			.unwrap_or_default()
+	}
+
+	fn count(&self, hit: bool) {
+		let mut stats = self.stats();
+		if hit {
+			stats.hits += 1;
+		} else {
+			stats.misses += 1;
+		}
+		if let Ok(data) = serde_json::to_string(&stats) {
+			if let Err(e) = std::fs::write(self.stats_path(), data) {
+				warn!("unable to update cache stats: {e}");
+			}
+		}
+	}
+
+	/// Every cached object with its diagnostics, least recently used first
+	fn entries(&self) -> Vec<Entry> {
+		let mut entries = BTreeMap::<String, Entry>::new();
+		let files = std::fs::read_dir(&self.dir)
+			.into_iter()
+			.flatten()
+			.flatten()
+			.filter(|dir| dir.file_type().is_ok_and(|kind| kind.is_dir()))
+			.flat_map(|dir| std::fs::read_dir(dir.path()).into_iter().flatten().flatten());
+		for file in files {
+			let Ok(md) = file.metadata() else {
+				continue;
+			};
+			// '<key>.o', '<key>.stderr' and temporary '<key>.o.<pid>' files
+			let name = file.file_name().to_string_lossy().into_owned();
+			let key = name.split('.').next().unwrap_or_default().to_owned();
+			let entry = entries.entry(key).or_insert(Entry {
+				last_use: SystemTime::UNIX_EPOCH,
+				size: 0,
+				files: vec![],
+			});
+			// Hits only touch the object
+			let modified = md.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+			if name.ends_with(".o") || entry.files.is_empty() {
+				entry.last_use = modified;
+			}
+			entry.size += md.len();
+			entry.files.push(file.path());
+		}
+		let mut entries: Vec<Entry> = entries.into_values().collect();
+		entries.sort_by_key(|entry| entry.last_use);
+		entries
+	}
+
+	/// Compile `src` into `target` with `compiler options`, reusing an object
+	/// built from the same preprocessed source and command line if there is
+	/// one. Preprocessing also writes the dependency file, so it's up to date
+	/// even on a hit. The compiler's diagnostics (replayed on a hit) are
+	/// appended to `output`.
+	pub fn compile(
+		&self,
+		compiler: &str,
+		options: &[String],
+		src: &Path,
+		target: &Path,
+		output: &mut String,
+	) -> Result<(), String> {
+		let preprocessed = cmd(compiler, preprocess_options(options))
+			.stdout_capture()
+			.stderr_capture()
+			.unchecked()
+			.run()
+			.map_err(|e| format!("'{compiler}' failed: {e}"))?;
+		if !preprocessed.status.success() {
+			// Let the real compile report the error
+			return run(compiler, options, output);
+		}
+
+		// Paths under the project root are hashed relative to it, so checkouts
+		// share objects. Not with debug info, which keeps the absolute paths.
+		let root = std::env::current_dir()
+			.map(|dir| format!("{}/", dir.display()))
+			.map_err(|e| format!("unable to get the current directory: {e}"))?;
+		let relative_to = (!has_debug_info(options)).then_some(root.as_str());
+		let key = self.key(compiler, options, src, target, &preprocessed.stdout, relative_to);
+		let object = self.dir.join(&key[..2]).join(format!("{key}.o"));
+		let cached_stderr = object.with_extension("stderr");
+
+		if object.exists() {
+			debug!("cache hit: {} ({key})", src.display());
+			std::fs::copy(&object, target)
+				.map_err(|e| format!("unable to copy '{}': {e}", object.display()))?;
+			if let Ok(cached) = std::fs::read_to_string(&cached_stderr) {
+				output.push_str(&cached.replace(ROOT, &root));
+			}
+			// Mark the object as recently used
+			if let Ok(file) = std::fs::File::options().append(true).open(&object) {
+				let _ = file.set_modified(SystemTime::now());
+			}
+			self.count(true);
+			return Ok(());
+		}
+
+		debug!("cache miss: {} ({key})", src.display());
+		let start = output.len();
+		run(compiler, options, output)?;
+		if let Err(e) = self.store(target, &object, &output[start..].replace(&root, ROOT)) {
+			warn!("unable to cache '{}': {e}", target.display());
+		}
+		self.count(false);
+		Ok(())
+	}
+
+	/// Hash of the compiler, the command line and the preprocessed source.
+	/// Paths of the object and dependency file are left out, and paths under
+	/// `root` (in the arguments and line markers) are hashed relative to it.
+	fn key(
+		&self,
+		program: &str,
+		args: &[String],
+		src: &Path,
+		target: &Path,
+		preprocessed: &[u8],
+		root: Option<&str>,
+	) -> String {
+		let src = src.display().to_string();
+		let target_path = target.display().to_string();
+		let depfile = target.with_extension("d").display().to_string();
+
+		let mut hasher = Sha256::new();
+		hasher.update(program);
+		hasher.update([0]);
+		hasher.update(compiler_identity(program, args));
+		for arg in args {
+			hasher.update([0]);
+			match arg {
+				arg if *arg == src => hasher.update("{source}"),
+				arg if *arg == target_path => hasher.update("{target}"),
+				arg if *arg == depfile => hasher.update("{depfile}"),
+				arg => match root {
+					Some(root) => hasher.update(arg.replace(root, "")),
+					None => hasher.update(arg),
+				},
+			}
+		}
+		hasher.update([0]);
+		match root {
+			Some(root) => hasher.update(relative_line_markers(preprocessed, root)),
+			None => hasher.update(preprocessed),
+		}
+		format!("{:x}", hasher.finalize())
+	}
+
+	/// Copy `target` into the cache. Written under a temporary name first since
+	/// other builds may be reading the cache at the same time.
+	fn store(&self, target: &Path, object: &Path, stderr: &str) -> std::io::Result<()> {
+		let dir = object.parent().expect("cache objects are in a subdirectory");
+		std::fs::create_dir_all(dir)?;
+
+		let temp = object.with_extension(format!("o.{}", std::process::id()));
+		std::fs::copy(target, &temp)?;
+		if stderr.is_empty() {
+			let _ = std::fs::remove_file(object.with_extension("stderr"));
+		} else {
+			std::fs::write(object.with_extension("stderr"), stderr)?;
+		}
+		std::fs::rename(&temp, object)
+	}
+
+	/// Evict the least recently used objects once the cache is over
+	/// `max_size`
+	pub fn trim(&self) {
+		let entries = self.entries();
+		let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+		if size <= self.max_size {
+			return;
+		}
+
+		let goal = (self.max_size as f64 * TRIM_RATIO) as u64;
+		trace!("trimming cache from {size} to {goal} bytes");
+		for entry in entries {
+			if size <= goal {
+				break;
+			}
+			// The object goes last so a half removed entry is never a hit
+			// without its diagnostics
+			let (objects, others): (Vec<&PathBuf>, Vec<&PathBuf>) = entry.files.iter()
+				.partition(|file| file.extension().is_some_and(|ext| ext == "o"));
+			for file in others.into_iter().chain(objects) {
+				let Ok(md) = std::fs::metadata(file) else {
+					continue;
+				};
+				if std::fs::remove_file(file).is_ok() {
+					size = size.saturating_sub(md.len());
+				}
+			}
+		}
+	}
+
+	pub fn print_stats(&self) {
+		let entries = self.entries();
+		let objects = entries.iter()
+			.filter(|entry| entry.files.iter().any(|file| file.extension().is_some_and(|ext| ext == "o")))
+			.count();
+		let size: u64 = entries.iter().map(|entry| entry.size).sum();
+		let stats = self.stats();
+		let lookups = stats.hits + stats.misses;
+
+		println!("directory: {}", self.dir.display());
+		println!("enabled:   {}", self.enabled);
+		println!("objects:   {objects}");
+		println!("size:      {:.1} MiB / {:.1} MiB", mib(size), mib(self.max_size));
+		println!("hits:      {}", stats.hits);
+		println!("misses:    {}", stats.misses);
+		if lookups > 0 {
+			println!("hit rate:  {:.1}%", stats.hits as f64 * 100.0 / lookups as f64);
+		}
+	}
+
+	pub fn clear(&self) -> std::io::Result<()> {
+		if self.dir.exists() {
+			std::fs::remove_dir_all(&self.dir)?;
+		}
+		println!("cleared {}", self.dir.display());
+		Ok(())
+	}
+}
+
+fn run(program: &str, args: &[String], output: &mut String) -> Result<(), String> {
+	graph::run_captured(cmd(program, args), program, output)
+		.map_err(|e| e.message)
+}
+
+/// `$XDG_CACHE_HOME/ssmake`, falling back to `~/.cache/ssmake`
+pub fn default_dir() -> PathBuf {
+	std::env::var_os("XDG_CACHE_HOME")
+		.map(PathBuf::from)
+		.or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+		.unwrap_or_else(std::env::temp_dir)
+		.join("ssmake")
+}
+
+/// Sizes like "512K", "300M" or "2G"
+fn parse_size(s: &str) -> Option<u64> {
+	let s = s.trim();
+	let (number, shift) = match s.char_indices().last()? {
+		(i, 'K' | 'k') => (&s[..i], 10),
+		(i, 'M' | 'm') => (&s[..i], 20),
+		(i, 'G' | 'g') => (&s[..i], 30),
+		_ => (s, 0),
+	};
+	number.trim().parse::<u64>().ok().map(|n| n << shift)
+}
+
+/// Size and modification time of the program and, with a launcher, of the
+/// compiler it runs (the arguments before the first option). A rebuilt or
+/// upgraded compiler at the same path gets new keys.
+fn compiler_identity(program: &str, args: &[String]) -> String {
+	let resolve = |program: &str| -> Option<PathBuf> {
+		let path = Path::new(program);
+		if path.components().count() > 1 {
+			return Some(path.to_owned());
+		}
+		std::env::split_paths(&std::env::var_os("PATH")?)
+			.map(|dir| dir.join(program))
+			.find(|candidate| candidate.is_file())
+	};
+	std::iter::once(program)
+		.chain(args.iter().map(String::as_str).take_while(|arg| !arg.starts_with('-')))
+		.map(|program| match resolve(program).and_then(|path| std::fs::metadata(path).ok()) {
+			Some(md) => format!("{program} {} {};", md.len(), mtime_nanos(&md)),
+			None => format!("{program};"),
+		})
+		.collect()
+}
+
+fn mtime_nanos(md: &std::fs::Metadata) -> u128 {
+	md.modified()
+		.ok()
+		.and_then(|date| date.duration_since(SystemTime::UNIX_EPOCH).ok())
+		.map(|date| date.as_nanos())
+		.unwrap_or_default()
+}
+
+/// Compile options turned into ones that preprocess to stdout. Line markers
+/// are kept, since the object's debug info and the diagnostics refer to them.
+fn preprocess_options(args: &[String]) -> Vec<String> {
+	let mut options = vec![];
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-c" | "-save-temps=obj" => {}
+			"-o" => { args.next(); }
+			_ => options.push(arg.clone()),
+		}
+	}
+	options.push("-E".into());
+	options
+}
+
+/// Whether `args` ask for debug info
+fn has_debug_info(args: &[String]) -> bool {
+	args.iter().any(|arg| arg.starts_with("-g") && arg != "-g0")
+}
+
+/// Preprocessed source with the `# <line> "<path>"` markers of files under
+/// `root` made relative to it
+fn relative_line_markers(preprocessed: &[u8], root: &str) -> Vec<u8> {
+	let marker = format!("\"{root}");
+	let mut normalized = Vec::with_capacity(preprocessed.len());
+	for line in preprocessed.split_inclusive(|&b| b == b'\n') {
+		match std::str::from_utf8(line) {
+			Ok(line) if line.starts_with("# ") => normalized.extend(line.replacen(&marker, "\"", 1).bytes()),
+			_ => normalized.extend_from_slice(line),
+		}
+	}
+	normalized
+}
+
+fn mib(bytes: u64) -> f64 {
+	bytes as f64 / (1 << 20) as f64
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_sizes() {
+		assert_eq!(parse_size("512K"), Some(512 << 10));
+		assert_eq!(parse_size("300m"), Some(300 << 20));
+		assert_eq!(parse_size(" 2G "), Some(2 << 30));
+		assert_eq!(parse_size("4096"), Some(4096));
+	}
+
+	#[test]
+	fn rejects_invalid_sizes() {
+		assert_eq!(parse_size(""), None);
+		assert_eq!(parse_size("G"), None);
+		assert_eq!(parse_size("1.5G"), None);
+		assert_eq!(parse_size("2T"), None);
+	}
+
+	#[test]
+	fn preprocesses_with_line_markers() {
+		let options = ["-std=c11", "-MD", "-c", "main.c", "-o", "build/main.o", "-save-temps=obj"]
+			.map(str::to_owned);
+		assert_eq!(preprocess_options(&options), ["-std=c11", "-MD", "main.c", "-E"]);
+	}
+
+	#[test]
+	fn makes_line_markers_relative() {
+		let preprocessed = b"# 1 \"/home/a/game/src/main.c\"\n# 1 \"/opt/yaul/include/yaul.h\" 1 3\nchar *s = \"/home/a/game/x\";\n";
+		assert_eq!(
+			relative_line_markers(preprocessed, "/home/a/game/"),
+			b"# 1 \"src/main.c\"\n# 1 \"/opt/yaul/include/yaul.h\" 1 3\nchar *s = \"/home/a/game/x\";\n",
+		);
+	}
+
+	#[test]
+	fn trims_objects_with_their_diagnostics() {
+		let dir = std::env::temp_dir().join(format!("ssmake-cache-{}", std::process::id()));
+		let cache = Cache { enabled: true, dir: dir.clone(), max_size: 100 };
+		std::fs::create_dir_all(dir.join("aa")).unwrap();
+		std::fs::create_dir_all(dir.join("bb")).unwrap();
+		let old = SystemTime::now() - std::time::Duration::from_secs(60);
+		for (file, len) in [("aa/aa1.o", 40), ("aa/aa1.stderr", 40), ("bb/bb2.o", 40), ("bb/bb2.stderr", 10)] {
+			std::fs::write(dir.join(file), vec![0; len]).unwrap();
+		}
+		// Last used a minute ago, though its diagnostics are as new as bb2
+		std::fs::File::options().append(true).open(dir.join("aa/aa1.o")).unwrap().set_modified(old).unwrap();
+
+		cache.trim();
+		let mut left: Vec<String> = cache.entries().into_iter()
+			.flat_map(|entry| entry.files)
+			.map(|file| file.file_name().unwrap().to_string_lossy().into_owned())
+			.collect();
+		left.sort();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(left, ["bb2.o", "bb2.stderr"]);
+	}
+}
@@ -0,0 +1,273 @@
+
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+use tracing::{trace, warn};
+
+use crate::memory;
+
+/// Value of `ip.1st-read-size`
+#[derive(Debug, Clone, Copy)]
+pub enum ReadSize {
+	/// Computed from the size of the built program binary
+	Auto,
+	/// Passed verbatim to `make-ip` (0 lets the BIOS read the whole file)
+	Fixed(u32),
+}
+
+/// Value of `build.up-to-date`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpToDate {
+	/// Rebuild when an input is newer than its output
+	Mtime,
+	/// Rebuild when the contents of an input changed, using the build database
+	Hash,
+}
+
+/// Value of `build.warnings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warnings {
+	/// Warnings fail the build (`-Werror`)
+	Deny,
+	/// Warnings are only reported
+	Warn,
+	/// Only warnings missing from the baseline file fail the build
+	Baseline,
+}
+
+/// Project configuration read from `config.toml`
+#[derive(Debug, Clone)]
+pub struct Config {
+	/// Path to `config.toml`
+	pub path: PathBuf,
+	/// The whole file, for the sections read by other modules
+	pub table: Table,
+
+	pub dir_image: PathBuf,
+	pub dir_audio: PathBuf,
+	pub dir_build: PathBuf,
+	pub dir_asset: PathBuf,
+	pub dir_output: PathBuf,
+
+	pub sh_program: String,
+	pub sh_symbols: Vec<String>,
+	pub sh_srcs: Vec<PathBuf>,
+
+	pub ip_version: String,
+	pub ip_release_date: u32,
+	pub ip_areas: String,
+	pub ip_peripherals: String,
+	pub ip_title: String,
+	pub ip_main_stack_addr: u32,
+	pub ip_sub_stack_addr: u32,
+	pub ip_1st_read_addr: u32,
+	pub ip_1st_read_size: ReadSize,
+
+	/// Builtin assets as (file, symbol name) pairs
+	pub assets: Vec<(String, String)>,
+
+	pub memory_budget: memory::Budget,
+
+	pub up_to_date: UpToDate,
+
+	pub warnings: Warnings,
+	/// Warnings accepted by `Warnings::Baseline`
+	pub warnings_baseline: PathBuf,
+}
+
+impl Config {
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+		let config = std::fs::read_to_string(path.as_ref())
+			.map_err(|e| format!("unable to read '{}': {e}", path.as_ref().display()))?
+			.parse::<Table>()
+			.map_err(|e| format!("unable to parse '{}': {e}", path.as_ref().display()))?;
+		let get = |section: &str, key: &str| config.get(section).and_then(|s| s.get(key));
+
+		fn missing_config_string<S: AsRef<str>>(property: &str, value: S) -> S {
+			warn!("missing {property} = \"value\" (string)");
+			value
+		}
+
+		fn missing_config_str_array<T>(property: &str) -> Vec<T> {
+			warn!("missing {property} = [] (string array)");
+			vec![]
+		}
+
+		fn missing_config_path<'a>(property: &str, value: &'a str) -> &'a str {
+			warn!("missing {property} = \"path\" (string)]");
+			value
+		}
+
+		fn missing_config_integer(property: &str, value: u32) -> u32 {
+			warn!("missing {property} = \"value\" (u32)");
+			value
+		}
+
+		// Project Directory Configuration
+		let dir_image  = PathBuf::from(get("dirs", "image").and_then(Value::as_str)  // ISO/CUE
+			.unwrap_or_else(|| missing_config_path("dirs.image", "cd")));
+		let dir_audio  = PathBuf::from(get("dirs", "audio").and_then(Value::as_str)  // ISO/CUE
+			.unwrap_or_else(|| missing_config_path("dirs.audio", "audio")));
+		let dir_build  = PathBuf::from(get("dirs", "build").and_then(Value::as_str)  // ISO/CUE
+			.unwrap_or_else(|| missing_config_path("dirs.build", "build")));
+		let dir_asset  = PathBuf::from(get("dirs", "assets").and_then(Value::as_str) // ISO/CUE
+			.unwrap_or_else(|| missing_config_path("dirs.assets", "assets")));
+		let dir_output = PathBuf::from(get("dirs", "output").and_then(Value::as_str) // ISO/CUE
+			.unwrap_or_else(|| missing_config_path("dirs.output", ".")));
+
+		trace!("project config");
+		trace!("  image  = '{}'", dir_asset.display());
+		trace!("  build  = '{}'", dir_build.display());
+		trace!("  asset  = '{}'", dir_asset.display());
+		trace!("  build  = '{}'", dir_build.display());
+		trace!("  output = '{}'", dir_output.display());
+
+		// SH2 Program Configuration
+		let sh_program = get("sh", "program").and_then(Value::as_str)
+			.ok_or("missing sh.program = \"name\" (string)")?;
+		// Not passed to the compiler, so it's optional
+		let sh_flags: Vec<String> = get("sh", "flags").and_then(Value::as_array)
+			.cloned()
+			.unwrap_or_default()
+			.into_iter()
+			.flat_map(|v| v.as_str().map(str::to_owned))
+			.collect();
+		let sh_symbols: Vec<String> = get("sh", "symbols")
+			.and_then(|v| v.as_array())
+			.cloned()
+			.unwrap_or_else(|| missing_config_str_array("sh.symbols"))
+			.into_iter()
+			.flat_map(|v| v.as_str().map(str::to_owned))
+			.collect();
+		let sh_srcs: Vec<PathBuf> = get("sh", "srcs").and_then(Value::as_array)
+			.ok_or("missing sh.srcs = [] (string array)")?
+			.iter()
+			.flat_map(Value::as_str)
+			.map(PathBuf::from)
+			.collect();
+
+		trace!("SH2 program config");
+		trace!("  program = '{sh_program}'");
+		trace!("  flags   = [{}]", sh_flags.join(","));
+		trace!("  symbols = [{}]", sh_symbols.join(","));
+		trace!("  sources = [{}]", sh_srcs.iter()
+			.map(|s| s.display().to_string())
+			.collect::<Vec<String>>()
+			.join(","));
+
+		// IP Configuration
+		let ip_version         = get("ip", "version").and_then(Value::as_str)             // ISO/CUE, SS
+			.unwrap_or_else(|| missing_config_string("ip.version", "V1.000"));
+		let ip_release_date    = get("ip", "release-date").and_then(Value::as_integer)        // ISO/CUE, SS
+			.map(|v| v as u32)
+			.unwrap_or_else(|| {
+				let date = chrono::Utc::now().format("%Y%m%d").to_string().parse::<u32>()
+					.expect("unable to convert current date to u32");
+				missing_config_integer("ip.release-date", date)
+			});
+		let ip_areas           = get("ip", "areas").and_then(Value::as_str)               // ISO/CUE, SS
+			.unwrap_or_else(|| missing_config_string("ip.areas", "JTUBKAEL"));
+		let ip_peripherals     = get("ip", "peripherals").and_then(Value::as_str)         // ISO/CUE, SS
+			.unwrap_or_else(|| missing_config_string("ip.peripherals", "JAMKST"));
+		let ip_title           = get("ip", "title").and_then(Value::as_str)               // ISO/CUE, SS
+			.unwrap_or_else(|| missing_config_string("ip.title", sh_program));
+		let ip_main_stack_addr = get("ip", "main-stack-addr").and_then(Value::as_integer) // ISO/CUE, SS
+			.map(|v| v as u32)
+			.unwrap_or_else(|| missing_config_integer("ip.main-stack-addr", 0x06004000));
+		let ip_sub_stack_addr  = get("ip", "sub-stack-addr").and_then(Value::as_integer)  // ISO/CUE, SS
+			.map(|v| v as u32)
+			.unwrap_or_else(|| missing_config_integer("ip.sub-stack-addr", 0x06001E00));
+		let ip_1st_read_addr   = get("ip", "1st-read-addr").and_then(Value::as_integer)   // ISO/CUE, SS
+			.map(|v| v as u32)
+			.unwrap_or_else(|| missing_config_integer("ip.1st-read-addr", 0x06004000));
+		let ip_1st_read_size   = match get("ip", "1st-read-size") {    // ISO/CUE, SS
+			Some(Value::Integer(v)) => ReadSize::Fixed(*v as u32),
+			Some(Value::String(v)) if v == "auto" => ReadSize::Auto,
+			Some(v) => return Err(format!("invalid ip.1st-read-size = {v} (expected u32 or \"auto\")")),
+			None => ReadSize::Fixed(missing_config_integer("ip.1st-read-size", 0)),
+		};
+
+		trace!("IP config");
+		trace!("  version            = '{ip_version}'");
+		trace!("  release-date       = '{ip_release_date}'");
+		trace!("  areas              = '{ip_areas}'");
+		trace!("  peripherals        = '{ip_peripherals}'");
+		trace!("  title              = '{ip_title}'");
+		trace!("  main-stack-address = '{ip_main_stack_addr}'");
+		trace!("  sub-stack-address  = '{ip_sub_stack_addr}'");
+		trace!("  1st-read-address   = '{ip_1st_read_addr}'");
+		trace!("  1st-read-size      = '{ip_1st_read_size:?}'");
+
+		let memory_budget = memory::Budget::from_config(&config);
+
+		let up_to_date = match config.get("build").and_then(|build| build.get("up-to-date")) {
+			Some(Value::String(v)) if v == "mtime" => UpToDate::Mtime,
+			Some(Value::String(v)) if v == "hash" => UpToDate::Hash,
+			Some(v) => return Err(format!("invalid build.up-to-date = {v} (expected \"mtime\" or \"hash\")")),
+			None => UpToDate::Mtime,
+		};
+		trace!("up-to-date check: {up_to_date:?}");
+
+		let warnings = match config.get("build").and_then(|build| build.get("warnings")) {
+			Some(Value::String(v)) if v == "deny" => Warnings::Deny,
+			Some(Value::String(v)) if v == "warn" => Warnings::Warn,
+			Some(Value::String(v)) if v == "baseline" => Warnings::Baseline,
+			Some(v) => return Err(format!("invalid build.warnings = {v} (expected \"deny\", \"warn\" or \"baseline\")")),
+			None => Warnings::Warn,
+		};
+		let warnings_baseline = PathBuf::from(config.get("build")
+			.and_then(|build| build.get("warnings-baseline"))
+			.and_then(Value::as_str)
+			.unwrap_or("warnings-baseline.json"));
+		trace!("warnings: {warnings:?} (baseline '{}')", warnings_baseline.display());
+
+		let assets: Vec<(String, String)> = config.get("assets").and_then(Value::as_array)
+			.cloned()
+			.unwrap_or_default()
+			.into_iter()
+			.flat_map(|v| {
+				let field = |key: &str| v.get(key).and_then(Value::as_str).map(str::to_owned);
+				field("file").zip(field("name"))
+			})
+			.collect();
+
+		Ok(Self {
+			dir_image,
+			dir_audio,
+			dir_build,
+			dir_asset,
+			dir_output,
+			sh_program: sh_program.to_owned(),
+			sh_symbols,
+			sh_srcs,
+			ip_version: ip_version.to_owned(),
+			ip_release_date,
+			ip_areas: ip_areas.to_owned(),
+			ip_peripherals: ip_peripherals.to_owned(),
+			ip_title: ip_title.to_owned(),
+			ip_main_stack_addr,
+			ip_sub_stack_addr,
+			ip_1st_read_addr,
+			ip_1st_read_size,
+			assets,
+			memory_budget,
+			up_to_date,
+			warnings,
+			warnings_baseline,
+			path: path.as_ref().to_path_buf(),
+			table: config,
+		})
+	}
+
+	/// Absolute path of `dirs.build`
+	pub fn build_path(&self) -> PathBuf {
+		std::path::absolute(&self.dir_build)
+			.unwrap_or_else(|_| self.dir_build.clone())
+	}
+
+	/// Absolute path of `dirs.output`
+	pub fn output_path(&self) -> PathBuf {
+		std::path::absolute(&self.dir_output)
+			.unwrap_or_else(|_| self.dir_output.clone())
+	}
+}
@@ -0,0 +1,248 @@
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use crate::diagnostic::{self, Diagnostic, Severity};
+use crate::graph::{Action, Failure, Node, Reporter};
+
+// Same escape codes as ss.mk's V_BEGIN_*/V_END
+const V_BEGIN_RED: &str = "\x1b[1;31m";
+const V_BEGIN_GREEN: &str = "\x1b[1;32m";
+const V_BEGIN_YELLOW: &str = "\x1b[1;33m";
+const V_BEGIN_MAGENTA: &str = "\x1b[1;35m";
+const V_BEGIN_CYAN: &str = "\x1b[1;36m";
+const V_BEGIN_WHITE: &str = "\x1b[1;37m";
+const V_END: &str = "\x1b[m";
+
+/// Number of steps listed in the verbose timing summary
+const SLOWEST_STEPS: usize = 5;
+
+/// Value of `--color`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+	/// Color when the console's stream is a terminal and `NO_COLOR` isn't set
+	#[default]
+	Auto,
+	Always,
+	Never,
+}
+
+impl Color {
+	pub fn parse(s: &str) -> Option<Self> {
+		match s {
+			"auto" => Some(Self::Auto),
+			"always" => Some(Self::Always),
+			"never" => Some(Self::Never),
+			_ => None,
+		}
+	}
+
+	fn enabled(self, stream: impl IsTerminal) -> bool {
+		match self {
+			Self::Auto => stream.is_terminal()
+				&& std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+			Self::Always => true,
+			Self::Never => false,
+		}
+	}
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+	/// Only errors
+	Quiet,
+	/// One line per step and a timing summary
+	#[default]
+	Normal,
+	/// Full commands and the slowest steps
+	Verbose,
+}
+
+/// Human readable build output, like ss.mk's SILENT mode
+pub struct Console {
+	/// `--color`, resolved against the stream the console prints to
+	color_choice: Color,
+	color: bool,
+	verbosity: Verbosity,
+	/// Print why each step runs
+	explain: bool,
+	/// Commands are printed rather than run
+	dry_run: bool,
+	/// Print to stderr, leaving stdout to machine-readable output
+	stderr: bool,
+	timings: RefCell<Vec<(String, Duration)>>,
+	/// Diagnostics already printed, so warnings from headers included by
+	/// several sources are shown once
+	seen: RefCell<HashSet<Diagnostic>>,
+	/// Warning and error counts per file
+	counts: RefCell<BTreeMap<String, (usize, usize)>>,
+	/// Output of the running step other than errors, only printed under
+	/// `--quiet` if the step fails
+	held: RefCell<Vec<String>>,
+}
+
+impl Console {
+	pub fn new(color: Color, verbosity: Verbosity, explain: bool, dry_run: bool) -> Self {
+		Self {
+			color_choice: color,
+			color: color.enabled(std::io::stdout()),
+			verbosity,
+			explain,
+			dry_run,
+			stderr: false,
+			timings: RefCell::default(),
+			seen: RefCell::default(),
+			counts: RefCell::default(),
+			held: RefCell::default(),
+		}
+	}
+
+	/// Print to stderr instead of stdout
+	pub fn on_stderr(mut self) -> Self {
+		self.stderr = true;
+		self.color = self.color_choice.enabled(std::io::stderr());
+		self
+	}
+
+	fn line(&self, line: String) {
+		if self.stderr {
+			eprintln!("{line}");
+		} else {
+			println!("{line}");
+		}
+	}
+
+	fn paint(&self, color: &str, s: &str) -> String {
+		if self.color {
+			format!("{color}{s}{V_END}")
+		} else {
+			s.to_owned()
+		}
+	}
+
+	/// Color the location and severity of a diagnostic line like GCC does
+	fn paint_diagnostic(&self, line: &str) -> String {
+		for (marker, color) in [
+			(": fatal error:", V_BEGIN_RED),
+			(": error:", V_BEGIN_RED),
+			(": warning:", V_BEGIN_MAGENTA),
+			(": note:", V_BEGIN_CYAN),
+		] {
+			if let Some(i) = line.find(marker) {
+				let (location, rest) = line.split_at(i);
+				let severity = &marker[2..];
+				return format!("{}: {}{}",
+					self.paint(V_BEGIN_WHITE, location),
+					self.paint(color, severity),
+					&rest[marker.len()..]);
+			}
+		}
+		line.to_owned()
+	}
+
+	fn print_summary(&self) {
+		let counts = self.counts.borrow();
+		if counts.is_empty() {
+			return;
+		}
+		let plural = |n: usize, what: &str| format!("{n} {what}{}", if n == 1 { "" } else { "s" });
+		self.line("diagnostics:".into());
+		for (file, (warnings, errors)) in counts.iter() {
+			let mut parts = vec![];
+			if *warnings > 0 {
+				parts.push(self.paint(V_BEGIN_MAGENTA, &plural(*warnings, "warning")));
+			}
+			if *errors > 0 {
+				parts.push(self.paint(V_BEGIN_RED, &plural(*errors, "error")));
+			}
+			self.line(format!("  {file}: {}", parts.join(", ")));
+		}
+	}
+}
+
+impl Reporter for Console {
+	fn started(&self, step: usize, total: usize, node: &Node, reason: &str) {
+		if self.verbosity > Verbosity::Quiet {
+			self.line(format!("[{step}/{total}] {}", self.paint(V_BEGIN_YELLOW, &node.name)));
+		}
+		if self.explain {
+			self.line(format!("  {}", self.paint(V_BEGIN_CYAN, reason)));
+		}
+	}
+
+	fn action(&self, _node: &Node, action: &Action) {
+		if self.dry_run || self.verbosity == Verbosity::Verbose {
+			self.line(format!("  {}", action.describe()));
+		}
+	}
+
+	fn output(&self, _node: &Node, output: &str) {
+		for block in diagnostic::blocks(output) {
+			let error = block.diagnostic.as_ref().is_some_and(|d| d.severity == Severity::Error);
+			if let Some(diagnostic) = block.diagnostic {
+				if !self.seen.borrow_mut().insert(diagnostic.clone()) {
+					continue;
+				}
+				let mut counts = self.counts.borrow_mut();
+				let (warnings, errors) = counts.entry(diagnostic.file).or_default();
+				match diagnostic.severity {
+					Severity::Error => *errors += 1,
+					Severity::Warning => *warnings += 1,
+					Severity::Note => {}
+				}
+			}
+			for line in block.lines {
+				if self.verbosity == Verbosity::Quiet && !error {
+					self.held.borrow_mut().push(self.paint_diagnostic(line));
+				} else {
+					eprintln!("{}", self.paint_diagnostic(line));
+				}
+			}
+		}
+	}
+
+	fn finished(&self, node: &Node, duration: Duration, result: &Result<(), Failure>) {
+		let held = std::mem::take(&mut *self.held.borrow_mut());
+		match result {
+			Ok(()) => self.timings.borrow_mut().push((node.name.clone(), duration)),
+			Err(_) => {
+				for line in held {
+					eprintln!("{line}");
+				}
+				eprintln!("{} {}", self.paint(V_BEGIN_RED, "failed:"), node.name);
+			}
+		}
+	}
+
+	fn done(&self, ran: usize, duration: Duration, result: &Result<usize, String>) {
+		if self.verbosity > Verbosity::Quiet {
+			self.print_summary();
+		}
+		self.seen.borrow_mut().clear();
+		self.counts.borrow_mut().clear();
+		if self.verbosity == Verbosity::Quiet {
+			return;
+		}
+
+		match result {
+			Ok(0) => self.line(self.paint(V_BEGIN_GREEN, "up to date")),
+			Ok(_) if self.dry_run => self.line(format!("{ran} step{} would run", if ran == 1 { "" } else { "s" })),
+			Ok(_) => self.line(format!("{} {ran} step{} in {:.2}s",
+				self.paint(V_BEGIN_GREEN, "finished"),
+				if ran == 1 { "" } else { "s" },
+				duration.as_secs_f64())),
+			Err(_) => self.line(format!("{} after {:.2}s", self.paint(V_BEGIN_RED, "build failed"), duration.as_secs_f64())),
+		}
+
+		if self.verbosity == Verbosity::Verbose {
+			let mut timings = self.timings.borrow_mut();
+			timings.sort_by_key(|(_, duration)| std::cmp::Reverse(*duration));
+			for (name, duration) in timings.iter().take(SLOWEST_STEPS) {
+				self.line(format!("  {:>8.2}s  {name}", duration.as_secs_f64()));
+			}
+		}
+		self.timings.borrow_mut().clear();
+	}
+}
@@ -0,0 +1,215 @@
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{trace, warn};
+
+use crate::graph::{Node, Policy};
+
+/// Recorded state of an input when its node last ran
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Input {
+	/// Nanoseconds since the epoch, used to skip hashing unchanged files
+	pub mtime: u128,
+	pub size: u64,
+	pub hash: String,
+}
+
+impl Input {
+	fn read(path: &Path) -> Option<Self> {
+		let md = std::fs::metadata(path).ok()?;
+		Some(Self {
+			mtime: mtime_of(&md),
+			size: md.len(),
+			hash: hash_file(path)?,
+		})
+	}
+
+	/// Same file contents, checking the mtime and size first
+	fn matches(&self, path: &Path) -> Option<Self> {
+		let md = std::fs::metadata(path).ok()?;
+		if self.mtime == mtime_of(&md) && self.size == md.len() {
+			return Some(self.clone());
+		}
+		let current = Self::read(path)?;
+		(current.hash == self.hash).then_some(current)
+	}
+
+	/// Current state of `path`, only hashing it when it differs from `recorded`
+	fn current(path: &Path, recorded: Option<&Self>) -> Option<Self> {
+		recorded.and_then(|recorded| recorded.matches(path))
+			.or_else(|| Self::read(path))
+	}
+}
+
+/// What a node's outputs were built from
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Entry {
+	/// Hash of the node's actions (command lines)
+	pub actions: String,
+	/// `None` for inputs that were missing
+	pub inputs: BTreeMap<PathBuf, Option<Input>>,
+}
+
+/// Build database kept in the build directory, keyed on each node's first
+/// output
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Database {
+	pub entries: BTreeMap<PathBuf, Entry>,
+}
+
+impl Database {
+	/// Path of the database in `build_path`
+	pub fn path(build_path: &Path) -> PathBuf {
+		build_path.join(".ssmake.db")
+	}
+
+	/// Load the database, starting over if it's missing or unreadable
+	pub fn load<P: AsRef<Path>>(path: P) -> Self {
+		let path = path.as_ref();
+		let Ok(data) = std::fs::read_to_string(path) else {
+			return Self::default();
+		};
+		serde_json::from_str(&data).unwrap_or_else(|e| {
+			warn!("ignoring '{}': {e}", path.display());
+			Self::default()
+		})
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+		let path = path.as_ref();
+		let data = serde_json::to_string(self)
+			.map_err(|e| format!("unable to serialize build database: {e}"))?;
+		std::fs::write(path, data)
+			.map_err(|e| format!("unable to write '{}': {e}", path.display()))
+	}
+}
+
+fn mtime_of(md: &std::fs::Metadata) -> u128 {
+	md.modified()
+		.ok()
+		.and_then(|date| date.duration_since(SystemTime::UNIX_EPOCH).ok())
+		.map(|date| date.as_nanos())
+		.unwrap_or_default()
+}
+
+fn hash_file(path: &Path) -> Option<String> {
+	let data = std::fs::read(path).ok()?;
+	Some(format!("{:x}", Sha256::digest(data)))
+}
+
+fn hash_actions(node: &Node) -> String {
+	let mut hasher = Sha256::new();
+	for action in node.actions.iter() {
+		hasher.update(action.describe());
+		hasher.update([0]);
+	}
+	format!("{:x}", hasher.finalize())
+}
+
+/// A node is stale when an output is missing, its actions changed, or the
+/// contents of an input differ from when it last ran. Touching a file or
+/// checking out an identical revision doesn't cause a rebuild.
+pub struct Hash {
+	path: PathBuf,
+	database: RefCell<Database>,
+	/// Inputs of the node being built, read before its actions ran
+	building: RefCell<BTreeMap<PathBuf, Option<Input>>>,
+}
+
+impl Hash {
+	pub fn load(build_path: &Path) -> Self {
+		let path = Database::path(build_path);
+		Self {
+			database: RefCell::new(Database::load(&path)),
+			building: RefCell::default(),
+			path,
+		}
+	}
+
+	pub fn save(&self) -> Result<(), String> {
+		self.database.borrow().save(&self.path)
+	}
+}
+
+impl Policy for Hash {
+	fn stale_reason(&self, node: &Node) -> Option<String> {
+		let Some(key) = node.outputs.first() else {
+			return Some("always runs (no outputs)".into());
+		};
+		if let Some(output) = node.outputs.iter().find(|output| !output.exists()) {
+			return Some(format!("'{}' is missing", output.display()));
+		}
+
+		let mut database = self.database.borrow_mut();
+		let Some(entry) = database.entries.get_mut(key) else {
+			return Some("not in the build database".into());
+		};
+		if entry.actions != hash_actions(node) {
+			return Some("command line changed".into());
+		}
+
+		let inputs = node.all_inputs();
+		if let Some(input) = inputs.iter().find(|input| !entry.inputs.contains_key(*input)) {
+			return Some(format!("'{}' is a new input", input.display()));
+		}
+		if let Some(input) = entry.inputs.keys().find(|input| !inputs.contains(input)) {
+			return Some(format!("'{}' is no longer an input", input.display()));
+		}
+		for input in inputs {
+			let recorded = entry.inputs.get_mut(&input)?;
+			match (recorded.as_ref(), input.exists()) {
+				(None, false) => {}
+				(None, true) => return Some(format!("'{}' was missing", input.display())),
+				(Some(_), false) => return Some(format!("'{}' is missing", input.display())),
+				(Some(state), true) => match state.matches(&input) {
+					// Remember the new mtime so the file isn't hashed again
+					Some(current) => *recorded = Some(current),
+					None => return Some(format!("'{}' changed", input.display())),
+				},
+			}
+		}
+		None
+	}
+
+	/// Inputs are read before the run, so one changing while the node builds
+	/// makes it stale next time
+	fn building(&self, node: &Node) {
+		let database = self.database.borrow();
+		let entry = node.outputs.first().and_then(|key| database.entries.get(key));
+		*self.building.borrow_mut() = node.all_inputs()
+			.into_iter()
+			.map(|input| {
+				let recorded = entry.and_then(|entry| entry.inputs.get(&input)).and_then(Option::as_ref);
+				let state = Input::current(&input, recorded);
+				(input, state)
+			})
+			.collect();
+	}
+
+	fn built(&self, node: &Node) {
+		let Some(key) = node.outputs.first() else {
+			return;
+		};
+
+		// The depfile is rewritten by the run and may list new inputs, which
+		// can only be read now
+		let mut building = self.building.take();
+		let inputs = node.all_inputs()
+			.into_iter()
+			.map(|input| {
+				let state = building.remove(&input).unwrap_or_else(|| Input::read(&input));
+				(input, state)
+			})
+			.collect();
+		trace!("recording {}", key.display());
+		self.database.borrow_mut().entries.insert(key.clone(), Entry {
+			actions: hash_actions(node),
+			inputs,
+		});
+	}
+}
@@ -0,0 +1,76 @@
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use toml::{Table, Value};
+use tracing::{trace, debug};
+
+/// GDB session configured with `[debug]`
+#[derive(Debug, Clone)]
+pub struct Debugger {
+	/// Path to the tool-chain's gdb
+	pub gdb: String,
+	/// Address of the emulator's GDB stub
+	pub remote: String,
+	/// Time given to the emulator to open its GDB stub before gdb connects
+	pub startup_delay: std::time::Duration,
+}
+
+impl Debugger {
+	pub fn from_config(config: &Table, default_gdb: String) -> Self {
+		let debug = config.get("debug");
+		let gdb = debug
+			.and_then(|d| d.get("gdb"))
+			.and_then(Value::as_str)
+			.map(str::to_owned)
+			.unwrap_or(default_gdb);
+		let remote = debug
+			.and_then(|d| d.get("remote"))
+			.and_then(Value::as_str)
+			.unwrap_or("localhost:1234")
+			.to_owned();
+		let startup_delay = debug
+			.and_then(|d| d.get("startup-delay"))
+			.and_then(Value::as_integer)
+			.map(|ms| std::time::Duration::from_millis(ms as u64))
+			.unwrap_or(std::time::Duration::from_secs(1));
+
+		trace!("debug config");
+		trace!("  gdb    = '{gdb}'");
+		trace!("  remote = '{remote}'");
+		trace!("  startup-delay = {startup_delay:?}");
+
+		Self { gdb, remote, startup_delay }
+	}
+
+	/// Write a `.gdbinit` loading the program's symbols, pointing gdb at the
+	/// directories of the sources the `objects` were built from, and
+	/// connecting to the emulator.
+	pub fn write_gdbinit(&self, path: &Path, elf: &Path, objects: &[PathBuf]) -> std::io::Result<()> {
+		let source_dirs: BTreeSet<PathBuf> = objects.iter()
+			.map(|obj| PathBuf::from(crate::build::revert_build_path(&obj.display().to_string())))
+			.filter_map(|src| src.parent().map(Path::to_path_buf))
+			.collect();
+
+		let mut script = String::new();
+		script.push_str("set confirm off\n");
+		script.push_str(&format!("file {}\n", elf.display()));
+		for dir in source_dirs {
+			script.push_str(&format!("directory {}\n", dir.display()));
+		}
+		script.push_str(&format!("target remote {}\n", self.remote));
+
+		std::fs::write(path, script)
+	}
+
+	/// Run gdb with the generated script until the user quits
+	pub fn launch(&self, gdbinit: &Path) -> std::io::Result<i32> {
+		debug!("  '{} -x {}'", self.gdb, gdbinit.display());
+
+		let output = cmd!(&self.gdb, "-x", gdbinit)
+			.unchecked()
+			.run()?;
+		Ok(output.status.code().unwrap_or(1))
+	}
+}
@@ -0,0 +1,237 @@
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::graph::{Action, Failure, Node, Reporter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+	Note,
+	Warning,
+	Error,
+}
+
+/// One `file:line:column: severity: message` line of GCC output
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Diagnostic {
+	pub file: String,
+	pub line: u32,
+	pub column: Option<u32>,
+	pub severity: Severity,
+	pub message: String,
+	/// Warning option that enabled the diagnostic, e.g. `-Wshadow`
+	pub option: Option<String>,
+}
+
+const SEVERITIES: [(&str, Severity); 4] = [
+	(": fatal error: ", Severity::Error),
+	(": error: ", Severity::Error),
+	(": warning: ", Severity::Warning),
+	(": note: ", Severity::Note),
+];
+
+impl Diagnostic {
+	/// Parse a single line, ignoring context lines ("In function ...") and
+	/// source excerpts
+	pub fn parse(line: &str) -> Option<Self> {
+		let (location, severity, message) = SEVERITIES.iter()
+			.flat_map(|(marker, severity)| line.find(marker)
+				.map(|i| (i, &line[..i], *severity, &line[i + marker.len()..])))
+			.min_by_key(|(i, ..)| *i)
+			.map(|(_, location, severity, message)| (location, severity, message))?;
+
+		let mut parts = location.rsplitn(3, ':');
+		let last = parts.next()?.parse::<u32>().ok()?;
+		let (file, line, column) = match parts.next()?.parse::<u32>() {
+			Ok(line) => (parts.next()?, line, Some(last)),
+			Err(_) => (location.rsplit_once(':')?.0, last, None),
+		};
+
+		let (message, option) = match message.strip_suffix(']').and_then(|m| m.rsplit_once(" [")) {
+			Some((message, option)) if option.starts_with("-W") => (message, Some(option.to_owned())),
+			_ => (message, None),
+		};
+
+		Some(Self {
+			file: file.to_owned(),
+			line,
+			column,
+			severity,
+			message: message.to_owned(),
+			option,
+		})
+	}
+}
+
+/// Every diagnostic in a tool's stderr
+pub fn parse(stderr: &str) -> Vec<Diagnostic> {
+	stderr.lines()
+		.flat_map(Diagnostic::parse)
+		.collect()
+}
+
+/// A diagnostic with the lines that belong to it: the context before it
+/// ("In function ..."), its source excerpt and any notes. Text that isn't
+/// a diagnostic (e.g. `make-ip` output) is a block without one.
+#[derive(Debug, Default, Clone)]
+pub struct Block<'a> {
+	pub lines: Vec<&'a str>,
+	pub diagnostic: Option<Diagnostic>,
+}
+
+/// Group tool output into blocks
+pub fn blocks(output: &str) -> Vec<Block<'_>> {
+	let mut blocks = vec![];
+	let mut context = vec![];
+	let mut current: Option<Block> = None;
+	for line in output.lines() {
+		match Diagnostic::parse(line) {
+			Some(diagnostic) if diagnostic.severity != Severity::Note => {
+				blocks.extend(current.take());
+				let mut lines = std::mem::take(&mut context);
+				lines.push(line);
+				current = Some(Block { lines, diagnostic: Some(diagnostic) });
+			}
+			// Notes and source excerpts belong to the diagnostic above them
+			Some(_) => match current.as_mut() {
+				Some(block) => block.lines.push(line),
+				None => context.push(line),
+			},
+			None if line.starts_with(char::is_whitespace) => match current.as_mut() {
+				Some(block) => block.lines.push(line),
+				None => context.push(line),
+			},
+			None => {
+				blocks.extend(current.take());
+				context.push(line);
+			}
+		}
+	}
+	blocks.extend(current);
+	if !context.is_empty() {
+		blocks.push(Block { lines: context, diagnostic: None });
+	}
+	blocks
+}
+
+/// Replace every `@`-encoded path under `build_path` in `text` with the path
+/// of the source it was built from
+pub fn revert_paths(text: &str, build_path: &Path) -> String {
+	let prefix = format!("{}/@", build_path.display());
+	let mut out = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find(&prefix) {
+		out.push_str(&rest[..start]);
+		rest = &rest[start..];
+		let end = rest.find(|c: char| c.is_whitespace() || ":'\"`‘’,)".contains(c))
+			.unwrap_or(rest.len());
+		out.push_str(&crate::build::revert_build_path(&rest[..end]));
+		rest = &rest[end..];
+	}
+	out.push_str(rest);
+	out
+}
+
+/// Forwards to another reporter with the build paths in tool output reverted
+pub struct RevertPaths<'r> {
+	pub build_path: PathBuf,
+	pub inner: &'r dyn Reporter,
+}
+
+impl Reporter for RevertPaths<'_> {
+	fn started(&self, step: usize, total: usize, node: &Node, reason: &str) {
+		self.inner.started(step, total, node, reason);
+	}
+
+	fn action(&self, node: &Node, action: &Action) {
+		self.inner.action(node, action);
+	}
+
+	fn output(&self, node: &Node, output: &str) {
+		self.inner.output(node, &revert_paths(output, &self.build_path));
+	}
+
+	fn finished(&self, node: &Node, duration: Duration, result: &Result<(), Failure>) {
+		self.inner.finished(node, duration, result);
+	}
+
+	fn done(&self, ran: usize, duration: Duration, result: &Result<usize, String>) {
+		self.inner.done(ran, duration, result);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// GCC 14 compiling a function with a shadowed parameter, an undeclared
+	/// identifier and unused declarations
+	const STDERR: &str = "\
+main.c: In function 'f':
+main.c:3:18: warning: declaration of 'x' shadows a parameter [-Wshadow]
+    3 |         for (int x = 0; x < 1; x++) {}
+      |                  ^
+main.c:1:18: note: shadowed declaration is here
+    1 | static int f(int x) {
+      |              ~~~~^
+main.c:4:16: error: 'y' undeclared (first use in this function)
+    4 |         return y;
+      |                ^
+main.c:4:16: note: each undeclared identifier is reported only once for each function it appears in
+main.c:2:13: warning: unused variable 'x2' [-Wunused-variable]
+    2 |         int x2;
+      |             ^~
+main.c: At top level:
+main.c:1:12: warning: 'f' defined but not used [-Wunused-function]
+    1 | static int f(int x) {
+      |            ^
+";
+
+	#[test]
+	fn parses_gcc_diagnostics() {
+		let diagnostics = parse(STDERR);
+		assert_eq!(diagnostics.len(), 6);
+		assert_eq!(diagnostics[0], Diagnostic {
+			file: "main.c".into(),
+			line: 3,
+			column: Some(18),
+			severity: Severity::Warning,
+			message: "declaration of 'x' shadows a parameter".into(),
+			option: Some("-Wshadow".into()),
+		});
+		assert_eq!(diagnostics[1].severity, Severity::Note);
+		assert_eq!(diagnostics[2].severity, Severity::Error);
+		assert_eq!(diagnostics[2].message, "'y' undeclared (first use in this function)");
+		assert_eq!(diagnostics[2].option, None);
+	}
+
+	#[test]
+	fn parses_diagnostics_without_a_column() {
+		// -fno-show-column
+		let diagnostic = Diagnostic::parse("/tmp/my:game/main.c:2: warning: unused variable 'x2' [-Wunused-variable]").unwrap();
+		assert_eq!((diagnostic.file.as_str(), diagnostic.line, diagnostic.column), ("/tmp/my:game/main.c", 2, None));
+
+		let diagnostic = Diagnostic::parse("main.c:1:1: fatal error: yaul.h: No such file or directory").unwrap();
+		assert_eq!(diagnostic.severity, Severity::Error);
+		assert_eq!(diagnostic.message, "yaul.h: No such file or directory");
+	}
+
+	#[test]
+	fn groups_context_excerpts_and_notes() {
+		let blocks = blocks(STDERR);
+		let lines: Vec<usize> = blocks.iter().map(|block| block.lines.len()).collect();
+		assert_eq!(lines, [7, 4, 3, 4]);
+		assert_eq!(blocks[0].lines[0], "main.c: In function 'f':");
+		assert_eq!(blocks[3].lines[0], "main.c: At top level:");
+		assert!(blocks.iter().all(|block| block.diagnostic.is_some()));
+	}
+
+	#[test]
+	fn reverts_build_paths() {
+		let text = "/tmp/proj/build/@tmp@proj@src@main.s:4: Error: bad expression";
+		assert_eq!(revert_paths(text, Path::new("/tmp/proj/build")), "/tmp/proj/src/main.s:4: Error: bad expression");
+	}
+}
@@ -0,0 +1,332 @@
+
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use toml::{Table, Value};
+
+use crate::debug::Debugger;
+use crate::emulator::{self, Emulator};
+use crate::libyaul::Libyaul;
+use crate::sslang::{self, Sslang};
+use crate::toolchain::Toolchain;
+
+/// Outcome of one check
+enum Status {
+	/// Found, with its version or location
+	Ok(String),
+	/// Missing or broken, with a suggested fix. Required checks fail the doctor.
+	Failed { problem: String, fix: String },
+}
+
+struct Check {
+	name: String,
+	required: bool,
+	status: Status,
+}
+
+/// `path` if it's absolute, otherwise the first match on `PATH`
+fn find_program(program: &str) -> Option<PathBuf> {
+	let path = Path::new(program);
+	if path.components().count() > 1 {
+		return path.is_file().then(|| path.to_owned());
+	}
+	std::env::split_paths(&std::env::var_os("PATH")?)
+		.map(|dir| dir.join(program))
+		.find(|candidate| candidate.is_file())
+}
+
+/// First line of `program --version`, if it prints one
+fn version(program: &Path) -> Option<String> {
+	let output = cmd!(program, "--version")
+		.stdin_null()
+		.stdout_capture()
+		.stderr_null()
+		.unchecked()
+		.run()
+		.ok()?;
+	String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.next()
+		.map(|line| line.trim().to_owned())
+		.filter(|line| !line.is_empty())
+}
+
+/// Look for `program`, asking it for its version if `versioned`. Yaul's
+/// scripts and emulators don't all understand `--version`.
+fn check_program(name: &str, program: &str, required: bool, versioned: bool, fix: &str) -> Check {
+	let status = match find_program(program) {
+		Some(path) => Status::Ok(match versioned.then(|| version(&path)).flatten() {
+			Some(version) => format!("{version} ({})", path.display()),
+			None => path.display().to_string(),
+		}),
+		None => Status::Failed {
+			problem: format!("'{program}' not found"),
+			fix: fix.to_owned(),
+		},
+	};
+	Check { name: name.to_owned(), required, status }
+}
+
+fn check_file(name: &str, path: &Path, fix: &str) -> Check {
+	let status = if path.is_file() {
+		Status::Ok(path.display().to_string())
+	} else {
+		Status::Failed {
+			problem: format!("'{}' not found", path.display()),
+			fix: fix.to_owned(),
+		}
+	};
+	Check { name: name.to_owned(), required: true, status }
+}
+
+/// GCC finds specs files in its library directories, and prints the bare
+/// name back when it can't
+fn check_specs(gcc: &str, specs: &str) -> Check {
+	let found = cmd!(gcc, format!("-print-file-name={specs}"))
+		.stdin_null()
+		.stderr_null()
+		.unchecked()
+		.read()
+		.ok()
+		.map(|path| PathBuf::from(path.trim()))
+		.filter(|path| path.is_absolute() && path.is_file());
+	let status = match found {
+		Some(path) => Status::Ok(path.display().to_string()),
+		None => Status::Failed {
+			problem: format!("'{specs}' isn't in the tool-chain's library directories"),
+			fix: "install libyaul into the tool-chain (e.g. 'make install' in the libyaul tree)".into(),
+		},
+	};
+	Check { name: specs.to_owned(), required: true, status }
+}
+
+/// Specs files of libyaul built from `libyaul.source`, which the build stages
+/// in the build directory rather than the tool-chain
+fn check_staged_specs(libyaul: &Libyaul, toolchain: &Toolchain, specs: &str) -> Check {
+	let path = libyaul.lib_dir(toolchain).join(specs);
+	let status = if path.is_file() {
+		Status::Ok(path.display().to_string())
+	} else {
+		Status::Failed {
+			problem: format!("'{}' isn't built yet", path.display()),
+			fix: format!("run 'ssmake build', which builds libyaul from '{}'", libyaul.source.display()),
+		}
+	};
+	Check { name: specs.to_owned(), required: false, status }
+}
+
+/// The `gcc -E -Wp,-v` probe `build` uses to find the system include
+/// directories
+fn check_include_dirs(gcc: &str) -> Check {
+	let output = cmd!(gcc, "-E", "-Wp,-v", "-")
+		.stdin_bytes(Vec::new())
+		.stdout_null()
+		.stderr_capture()
+		.unchecked()
+		.run();
+	let status = match output {
+		Ok(output) if output.status.success() => {
+			let dirs = String::from_utf8_lossy(&output.stderr)
+				.lines()
+				.filter(|line| line.starts_with(char::is_whitespace))
+				.count();
+			Status::Ok(format!("{dirs} system include directories"))
+		}
+		Ok(output) => Status::Failed {
+			problem: format!("'{gcc} -E -Wp,-v -' failed ({})", output.status),
+			fix: "check that the compiler runs on its own".into(),
+		},
+		Err(e) => Status::Failed {
+			problem: format!("unable to run '{gcc}': {e}"),
+			fix: "check that the compiler is installed and executable".into(),
+		},
+	};
+	Check { name: "include directories".into(), required: true, status }
+}
+
+/// The emulators named in `config.toml`, or the known ones when there is no
+/// project
+fn check_emulators(config: Option<&Table>) -> Vec<Check> {
+	let mut names: Vec<String> = config
+		.into_iter()
+		.flat_map(|config| config.get("run")
+			.and_then(|run| run.get("emulator"))
+			.and_then(Value::as_str)
+			.map(str::to_owned)
+			.into_iter()
+			.chain(config.get("emulators")
+				.and_then(Value::as_table)
+				.into_iter()
+				.flat_map(|emulators| emulators.keys().cloned())))
+		.collect();
+	names.sort();
+	names.dedup();
+	let configured = !names.is_empty();
+	if !configured {
+		names = emulator::KNOWN_EMULATORS.iter().map(|(name, ..)| name.to_string()).collect();
+	}
+
+	let empty = Table::new();
+	names.iter()
+		.map(|name| match Emulator::from_config(config.unwrap_or(&empty), Some(name)) {
+			Ok(emulator) => check_program(&format!("emulator '{name}'"), &emulator.path, false, false,
+				&format!("install {name} or set emulators.{name}.path")),
+			Err(e) => Check {
+				name: format!("emulator '{name}'"),
+				required: configured,
+				status: Status::Failed { problem: e, fix: format!("fix [emulators.{name}] in config.toml") },
+			},
+		})
+		.collect()
+}
+
+/// The sslang compiler, if the project has sslang sources
+fn check_sslang(config: Option<&Table>) -> Option<Check> {
+	let config = config?;
+	let has_sources = config.get("sh")
+		.and_then(|sh| sh.get("srcs"))
+		.and_then(Value::as_array)?
+		.iter()
+		.flat_map(Value::as_str)
+		.any(|src| Path::new(src).extension().is_some_and(|ext| ext == sslang::EXTENSION));
+	has_sources.then(|| match Sslang::from_config(config) {
+		Ok(sslang) => check_program("sslang", &sslang.compiler, true, true, "install sslang or set sslang.compiler"),
+		Err(e) => Check {
+			name: "sslang".into(),
+			required: true,
+			status: Status::Failed { problem: e, fix: "fix [sslang] in config.toml".into() },
+		},
+	})
+}
+
+/// Programs and files of the tool-chain and Yaul
+fn check_toolchain(toolchain: &Toolchain, config: &Table) -> Vec<Check> {
+	let root = &toolchain.install_root;
+	let toolchain_fix = format!("install the SH-2 tool-chain into '{root}'");
+
+	let mut checks = vec![];
+	for tool in ["gcc", "g++", "gcc-nm", "objcopy", "objdump"] {
+		checks.push(check_program(tool, &toolchain.program(tool), true, true, &toolchain_fix));
+	}
+	for tool in ["bin2o", "make-ip", "make-iso", "make-cue"] {
+		checks.push(check_program(tool, &toolchain.tool(tool), true, false,
+			&format!("install Yaul's tools into '{root}/bin'")));
+	}
+	checks.push(check_program("wrap-error", &toolchain.share("wrap-error"), false, false,
+		"only needed by Yaul's Makefiles"));
+	checks.push(check_program("xorrisofs", "xorrisofs", true, true,
+		"install xorriso, which make-iso uses (e.g. 'apt install xorriso')"));
+	let gdb = Debugger::from_config(config, toolchain.program("gdb")).gdb;
+	checks.push(check_program("gdb", &gdb, false, true,
+		"build the tool-chain with gdb, or set debug.gdb, to use 'debug'"));
+
+	let build_dir = config.get("dirs")
+		.and_then(|dirs| dirs.get("build"))
+		.and_then(Value::as_str)
+		.unwrap_or("build");
+	let libyaul = Libyaul::from_config(config, Path::new(build_dir)).unwrap_or_else(|e| {
+		checks.push(Check {
+			name: "libyaul".into(),
+			required: true,
+			status: Status::Failed { problem: e, fix: "fix [libyaul] in config.toml".into() },
+		});
+		None
+	});
+	let gcc = toolchain.program("gcc");
+	if find_program(&gcc).is_some() {
+		checks.push(check_include_dirs(&gcc));
+		for specs in ["yaul.specs", "yaul-main.specs", "yaul-main-c++.specs"] {
+			checks.push(match &libyaul {
+				Some(libyaul) => check_staged_specs(libyaul, toolchain, specs),
+				None => check_specs(&gcc, specs),
+			});
+		}
+	}
+	checks.push(check_file("ip.sx", Path::new(&toolchain.share("yaul/ip/ip.sx")),
+		"install libyaul, which provides the IP template"));
+	checks
+}
+
+/// Check every tool and file the build uses, printing what's missing and how
+/// to fix it. Returns whether every required check passed.
+pub fn doctor(config_path: &Path) -> bool {
+	let empty = Table::new();
+	let mut checks = vec![];
+
+	// The project is optional, it only adds the emulators and sslang to check.
+	// The configuration is read without validating it, so a broken one is
+	// reported like any other problem.
+	let config = config_path.exists().then(|| {
+		let data = std::fs::read_to_string(config_path).map_err(|e| e.to_string())?;
+		data.parse::<Table>().map_err(|e| {
+			// The full report quotes the line, which doesn't fit the table
+			let report = e.to_string();
+			let location = report.lines().next().unwrap_or_default();
+			format!("{location}: {}", e.message().trim_end().replace('\n', ", "))
+		})
+	});
+	let config = match config {
+		Some(Ok(config)) => {
+			checks.push(Check {
+				name: "config".into(),
+				required: true,
+				status: Status::Ok(config_path.display().to_string()),
+			});
+			Some(config)
+		}
+		Some(Err(e)) => {
+			checks.push(Check {
+				name: "config".into(),
+				required: true,
+				status: Status::Failed {
+					problem: format!("unable to read '{}': {e}", config_path.display()),
+					fix: format!("fix the syntax of '{}'", config_path.display()),
+				},
+			});
+			None
+		}
+		None => None,
+	};
+	let config = config.as_ref();
+
+	match Toolchain::discover(config.unwrap_or(&empty)) {
+		Ok(toolchain) => {
+			checks.push(Check {
+				name: "tool-chain".into(),
+				required: true,
+				status: Status::Ok(format!("{} ({})", toolchain.install_root, toolchain.prog_prefix)),
+			});
+			checks.extend(check_toolchain(&toolchain, config.unwrap_or(&empty)));
+		}
+		Err(e) => checks.push(Check {
+			name: "tool-chain".into(),
+			required: true,
+			status: Status::Failed {
+				problem: e,
+				fix: "install the SH-2 tool-chain, or point YAUL_INSTALL_ROOT at it".into(),
+			},
+		}),
+	}
+	checks.extend(check_sslang(config));
+	checks.extend(check_emulators(config));
+
+	let width = checks.iter().map(|check| check.name.len()).max().unwrap_or(0);
+	let mut healthy = true;
+	for check in checks.iter() {
+		match &check.status {
+			Status::Ok(detail) => println!("  ok       {:width$}  {detail}", check.name),
+			Status::Failed { problem, fix } => {
+				let label = if check.required { "missing" } else { "optional" };
+				println!("  {label:8} {:width$}  {problem}", check.name);
+				println!("  {:8} {:width$}  fix: {fix}", "", "");
+				healthy &= !check.required;
+			}
+		}
+	}
+	if healthy {
+		println!("everything ssmake needs was found");
+	} else {
+		println!("some required tools or files are missing");
+	}
+	healthy
+}
@@ -0,0 +1,159 @@
+
+use std::path::Path;
+
+/// Section occupies memory during execution
+const SHF_ALLOC: u32 = 0x2;
+
+/// Section occupies no space in the file (.bss)
+const SHT_NOBITS: u32 = 8;
+
+/// A section header from an ELF32 file
+#[derive(Debug, Clone)]
+pub struct Section {
+	pub name: String,
+	pub addr: u32,
+	pub size: u32,
+	pub flags: u32,
+	pub kind: u32,
+}
+
+impl Section {
+	/// Whether the section is loaded into memory
+	pub fn is_alloc(&self) -> bool {
+		self.flags & SHF_ALLOC != 0
+	}
+
+	/// Whether the section only reserves memory (.bss and friends)
+	pub fn is_nobits(&self) -> bool {
+		self.kind == SHT_NOBITS
+	}
+}
+
+/// Read the section headers of an ELF32 file. Both byte orders are accepted,
+/// though the SH-2 tool-chain only produces big-endian files.
+pub fn read_sections<P: AsRef<Path>>(path: P) -> Result<Vec<Section>, String> {
+	let path = path.as_ref();
+	let data = std::fs::read(path)
+		.map_err(|e| format!("unable to read '{}': {e}", path.display()))?;
+	parse_sections(&data)
+		.map_err(|e| format!("'{}': {e}", path.display()))
+}
+
+fn parse_sections(data: &[u8]) -> Result<Vec<Section>, String> {
+	if data.len() < 52 || &data[..4] != b"\x7fELF" {
+		return Err("not an ELF file".into());
+	}
+	if data[4] != 1 {
+		return Err("not a 32-bit ELF file".into());
+	}
+	let big_endian = match data[5] {
+		1 => false,
+		2 => true,
+		_ => return Err("unknown ELF byte order".into()),
+	};
+
+	let u16_at = |at: usize| -> Result<u16, String> {
+		let bytes: [u8; 2] = data.get(at..at + 2)
+			.ok_or("truncated ELF file")?
+			.try_into()
+			.unwrap();
+		Ok(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+	};
+	let u32_at = |at: usize| -> Result<u32, String> {
+		let bytes: [u8; 4] = data.get(at..at + 4)
+			.ok_or("truncated ELF file")?
+			.try_into()
+			.unwrap();
+		Ok(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+	};
+
+	let shoff = u32_at(0x20)? as usize;
+	let shentsize = u16_at(0x2E)? as usize;
+	let shnum = u16_at(0x30)? as usize;
+	let shstrndx = u16_at(0x32)? as usize;
+
+	let mut sections = Vec::with_capacity(shnum);
+	let mut name_offsets = Vec::with_capacity(shnum);
+	for i in 0..shnum {
+		let header = shoff + i * shentsize;
+		name_offsets.push(u32_at(header)? as usize);
+		sections.push(Section {
+			name: String::new(),
+			kind: u32_at(header + 0x04)?,
+			flags: u32_at(header + 0x08)?,
+			addr: u32_at(header + 0x0C)?,
+			size: u32_at(header + 0x14)?,
+		});
+	}
+
+	if shstrndx < shnum {
+		let strtab = shoff + shstrndx * shentsize;
+		let strtab_offset = u32_at(strtab + 0x10)? as usize;
+		for (section, offset) in sections.iter_mut().zip(name_offsets) {
+			let start = strtab_offset + offset;
+			let name = data.get(start..)
+				.and_then(|rest| rest.split(|&b| b == 0).next())
+				.ok_or("truncated section name table")?;
+			section.name = String::from_utf8_lossy(name).into_owned();
+		}
+	}
+
+	Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A big-endian ELF32 file with a null section, `.text`, `.bss` and the
+	/// section name table
+	fn elf() -> Vec<u8> {
+		let names = b"\0.text\0.bss\0.shstrtab\0";
+		let shoff = 52 + names.len();
+		let mut data = vec![0u8; 52];
+		data[..6].copy_from_slice(b"\x7fELF\x01\x02");
+		data[0x20..0x24].copy_from_slice(&(shoff as u32).to_be_bytes());
+		data[0x2E..0x30].copy_from_slice(&40u16.to_be_bytes());
+		data[0x30..0x32].copy_from_slice(&4u16.to_be_bytes());
+		data[0x32..0x34].copy_from_slice(&3u16.to_be_bytes());
+		data.extend_from_slice(names);
+
+		let mut section = |name: u32, kind: u32, flags: u32, addr: u32, offset: u32, size: u32| {
+			let mut header = [0u8; 40];
+			for (at, value) in [(0x00, name), (0x04, kind), (0x08, flags), (0x0C, addr), (0x10, offset), (0x14, size)] {
+				header[at..at + 4].copy_from_slice(&value.to_be_bytes());
+			}
+			data.extend_from_slice(&header);
+		};
+		section(0, 0, 0, 0, 0, 0);
+		section(1, 1, SHF_ALLOC | 0x4, 0x0600_4000, 0, 0x1234);
+		section(7, SHT_NOBITS, SHF_ALLOC | 0x1, 0x0600_5234, 0, 0x100);
+		section(12, 3, 0, 0, 52, names.len() as u32);
+		data
+	}
+
+	#[test]
+	fn parses_section_headers() {
+		let sections = parse_sections(&elf()).unwrap();
+		let names: Vec<&str> = sections.iter().map(|s| s.name.as_str()).collect();
+		assert_eq!(names, ["", ".text", ".bss", ".shstrtab"]);
+
+		let text = &sections[1];
+		assert_eq!((text.addr, text.size), (0x0600_4000, 0x1234));
+		assert!(text.is_alloc() && !text.is_nobits());
+		assert!(sections[2].is_nobits());
+		assert!(!sections[3].is_alloc());
+	}
+
+	#[test]
+	fn rejects_other_files() {
+		assert!(parse_sections(b"#!/bin/sh\n").is_err());
+
+		let mut elf64 = elf();
+		elf64[4] = 2;
+		assert!(parse_sections(&elf64).is_err());
+
+		let truncated = &elf()[..80];
+		assert!(parse_sections(truncated).is_err());
+	}
+}
@@ -0,0 +1,123 @@
+
+use std::path::Path;
+
+use duct::cmd;
+use toml::{Table, Value};
+use tracing::{trace, debug};
+
+/// Argument templates for emulators we know about. `{cue}`, `{iso}`, `{bin}`
+/// and `{elf}` are replaced with the paths of the build outputs.
+pub const KNOWN_EMULATORS: &[(&str, &str, &[&str])] = &[
+	("mednafen", "mednafen", &["{cue}"]),
+	("yabause",  "yabause",  &["-a", "-i", "{cue}"]),
+	("kronos",   "kronos",   &["-a", "-i", "{cue}"]),
+	("ymir",     "ymir",     &["{cue}"]),
+];
+
+/// Emulator launched by `run`, configured with `[run]` and `[emulators.<name>]`
+#[derive(Debug, Clone)]
+pub struct Emulator {
+	pub name: String,
+	pub path: String,
+	pub args: Vec<String>,
+	/// Arguments used by `debug`, which should enable the emulator's GDB stub
+	pub debug_args: Vec<String>,
+}
+
+/// Build outputs an emulator can be pointed at
+pub struct Artifacts<'a> {
+	pub cue: &'a Path,
+	pub iso: &'a Path,
+	pub bin: &'a Path,
+	pub elf: &'a Path,
+}
+
+impl Emulator {
+	/// Look up the emulator `name`, or `run.emulator` if no name is given.
+	/// Settings in `[emulators.<name>]` override the built-in templates.
+	pub fn from_config(config: &Table, name: Option<&str>) -> Result<Self, String> {
+		let name = name
+			.or_else(|| config.get("run")
+				.and_then(|run| run.get("emulator"))
+				.and_then(Value::as_str))
+			.ok_or("missing run.emulator = \"name\" (string)")?;
+
+		let known = KNOWN_EMULATORS.iter()
+			.find(|(known, _, _)| *known == name);
+		let table = config.get("emulators")
+			.and_then(|emulators| emulators.get(name));
+
+		if known.is_none() && table.is_none() {
+			return Err(format!("unknown emulator '{name}' (add an [emulators.{name}] table)"));
+		}
+
+		let path = table
+			.and_then(|t| t.get("path"))
+			.and_then(Value::as_str)
+			.or(known.map(|(_, path, _)| *path))
+			.ok_or(format!("missing emulators.{name}.path = \"path\" (string)"))?
+			.to_owned();
+
+		let string_array = |key: &str| table
+			.and_then(|t| t.get(key))
+			.and_then(Value::as_array)
+			.map(|args| args.iter()
+				.flat_map(Value::as_str)
+				.map(str::to_owned)
+				.collect::<Vec<String>>());
+
+		let args = string_array("args").unwrap_or_else(|| known
+			.map(|(_, _, args)| args.iter().map(|s| s.to_string()).collect())
+			.unwrap_or_else(|| vec!["{cue}".into()]));
+		let debug_args = string_array("debug-args").unwrap_or_else(|| args.clone());
+
+		trace!("emulator config");
+		trace!("  name = '{name}'");
+		trace!("  path = '{path}'");
+		trace!("  args = [{}]", args.join(","));
+		trace!("  debug-args = [{}]", debug_args.join(","));
+
+		Ok(Self { name: name.to_owned(), path, args, debug_args })
+	}
+
+	/// Fill in the argument templates
+	pub fn command_line(&self, args: &[String], artifacts: &Artifacts, extra: &[String]) -> Vec<String> {
+		args.iter()
+			.map(|arg| arg
+				.replace("{cue}", &artifacts.cue.display().to_string())
+				.replace("{iso}", &artifacts.iso.display().to_string())
+				.replace("{bin}", &artifacts.bin.display().to_string())
+				.replace("{elf}", &artifacts.elf.display().to_string()))
+			.chain(extra.iter().cloned())
+			.collect()
+	}
+
+	/// Run the emulator until it exits, returning its exit code
+	pub fn launch(&self, artifacts: &Artifacts, extra: &[String]) -> std::io::Result<i32> {
+		let args = self.command_line(&self.args, artifacts, extra);
+		debug!("  '{} {}'", self.path, args.join(" "));
+
+		let output = cmd(&self.path, args)
+			.unchecked()
+			.run()?;
+		Ok(output.status.code().unwrap_or(1))
+	}
+
+	/// Start the emulator without waiting for it
+	pub fn start(&self, artifacts: &Artifacts, extra: &[String]) -> std::io::Result<duct::Handle> {
+		self.spawn(self.command_line(&self.args, artifacts, extra))
+	}
+
+	/// Start the emulator with its debug arguments without waiting for it
+	pub fn start_for_debug(&self, artifacts: &Artifacts, extra: &[String]) -> std::io::Result<duct::Handle> {
+		self.spawn(self.command_line(&self.debug_args, artifacts, extra))
+	}
+
+	fn spawn(&self, args: Vec<String>) -> std::io::Result<duct::Handle> {
+		debug!("  '{} {}'", self.path, args.join(" "));
+
+		cmd(&self.path, args)
+			.unchecked()
+			.start()
+	}
+}
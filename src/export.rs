@@ -0,0 +1,159 @@
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use tracing::info;
+
+use crate::build::{self, Context};
+use crate::config::Config;
+use crate::graph::{quote, Action};
+use crate::tool::{Invocation, Job};
+
+/// First line of every exported file, so re-exporting may replace it
+const HEADER: &str = "# Generated by 'ssmake export' from config.toml. Do not edit.";
+
+/// Value of `export --ninja`/`--makefile`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+	Ninja,
+	Makefile,
+}
+
+impl Format {
+	pub fn default_path(self) -> &'static Path {
+		match self {
+			Self::Ninja => Path::new("build.ninja"),
+			Self::Makefile => Path::new("Makefile"),
+		}
+	}
+}
+
+/// A build edge: shell commands producing `outputs` from `inputs`
+struct Step {
+	description: String,
+	inputs: Vec<PathBuf>,
+	outputs: Vec<PathBuf>,
+	depfile: Option<PathBuf>,
+	commands: Vec<String>,
+}
+
+fn display(path: &Path) -> String {
+	path.display().to_string()
+}
+
+/// The nodes of `build::STAGES` as shell commands. Calls without a shell
+/// equivalent, like the memory budget check and size report, are left out,
+/// while tools running in-process can't be exported at all.
+fn steps(ctx: &Context) -> Result<Vec<Step>, String> {
+	let mut tools = HashMap::<PathBuf, &str>::new();
+	for Job { tool, src, output, .. } in ctx.jobs() {
+		if let Invocation::InProcess { .. } = tool.invocation(ctx, &src, &output) {
+			return Err(format!("'{}' is built by {} inside ssmake, which can't be exported", src.display(), tool.name()));
+		}
+		tools.insert(output, tool.name());
+	}
+	let mut steps = vec![];
+	for stage in build::STAGES {
+		for node in stage.nodes(ctx) {
+			let commands: Vec<String> = node.actions.iter()
+				.flat_map(Action::shell)
+				.collect();
+			if commands.is_empty() {
+				continue;
+			}
+			let tool = node.outputs.first().and_then(|output| tools.get(output));
+			steps.push(Step {
+				description: match tool {
+					Some(tool) => format!("{tool} {}", node.name),
+					None => node.name,
+				},
+				// Recorded by 'ssmake build' alone
+				inputs: node.inputs.into_iter()
+					.filter(|input| *input != ctx.build_toolchain)
+					.collect(),
+				outputs: node.outputs,
+				depfile: node.depfile,
+				commands,
+			});
+		}
+	}
+	Ok(steps)
+}
+
+fn ninja_path(path: &Path) -> String {
+	display(path).replace('$', "$$").replace(' ', "$ ").replace(':', "$:")
+}
+
+fn ninja(ctx: &Context, steps: &[Step]) -> String {
+	let mut out = format!("{HEADER}\n\nninja_required_version = 1.3\n\n");
+	out += "rule run\n  command = $cmd\n  description = $desc\n\n";
+	out += "rule compile\n  command = $cmd\n  description = $desc\n  depfile = $depfile\n  deps = gcc\n\n";
+	for step in steps {
+		let paths = |paths: &[PathBuf]| paths.iter()
+			.map(|path| ninja_path(path))
+			.collect::<Vec<String>>()
+			.join(" ");
+		let rule = if step.depfile.is_some() { "compile" } else { "run" };
+		out += &format!("build {}: {rule} {}\n", paths(&step.outputs), paths(&step.inputs));
+		out += &format!("  cmd = {}\n", step.commands.join(" && ").replace('$', "$$"));
+		out += &format!("  desc = {}\n", step.description.replace('$', "$$"));
+		if let Some(depfile) = &step.depfile {
+			out += &format!("  depfile = {}\n", ninja_path(depfile));
+		}
+		out += "\n";
+	}
+	out += &format!("default {}\n", ninja_path(&ctx.out_program_cue));
+	out
+}
+
+fn make_path(path: &Path) -> String {
+	display(path).replace('$', "$$").replace(' ', "\\ ")
+}
+
+fn makefile(ctx: &Context, steps: &[Step]) -> String {
+	let mut out = format!("{HEADER}\n\n.PHONY: all\nall: {}\n\n", make_path(&ctx.out_program_cue));
+	for step in steps {
+		let paths = |paths: &[PathBuf]| paths.iter()
+			.map(|path| make_path(path))
+			.collect::<Vec<String>>()
+			.join(" ");
+		// '&:' (grouped targets) runs the recipe once for all outputs
+		let separator = if step.outputs.len() > 1 { "&:" } else { ":" };
+		out += &format!("{}{separator} {}\n", paths(&step.outputs), paths(&step.inputs));
+		out += &format!("\t@echo {}\n", quote(&step.description).replace('$', "$$"));
+		for command in step.commands.iter() {
+			out += &format!("\t@{}\n", command.replace('$', "$$"));
+		}
+		out += "\n";
+	}
+
+	let depfiles: Vec<String> = steps.iter()
+		.flat_map(|step| step.depfile.as_deref())
+		.map(make_path)
+		.collect();
+	if !depfiles.is_empty() {
+		out += &format!("-include {}\n", depfiles.join(" "));
+	}
+	out
+}
+
+/// Write the pipeline for `config` as a Ninja or Make build file. An existing
+/// file is only replaced if it was exported before.
+pub fn export(config: &Config, format: Format, output: &Path) -> Result<(), String> {
+	if let Ok(existing) = std::fs::read_to_string(output) {
+		if !existing.starts_with(HEADER) {
+			return Err(format!("'{}' already exists and wasn't generated by ssmake", output.display()));
+		}
+	}
+
+	let ctx = Context::new(config, false).map_err(|e| e.to_string())?;
+	let steps = steps(&ctx)?;
+	let text = match format {
+		Format::Ninja => ninja(&ctx, &steps),
+		Format::Makefile => makefile(&ctx, &steps),
+	};
+	std::fs::write(output, text)
+		.map_err(|e| format!("unable to write '{}': {e}", output.display()))?;
+	info!("wrote '{}'", output.display());
+	Ok(())
+}
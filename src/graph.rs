@@ -0,0 +1,609 @@
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use duct::cmd;
+use tracing::{trace, debug};
+
+/// Rust code run by [`Action::Call`], which may append what it or the tools
+/// it runs print to the given string
+pub type CallFn<'a> = Box<dyn Fn(&mut String) -> Result<(), String> + 'a>;
+
+/// One step of a node
+pub enum Action<'a> {
+	/// Run a program, optionally redirecting its output into a file
+	Command {
+		program: String,
+		args: Vec<String>,
+		stdout: Option<PathBuf>,
+		/// Swallow the program's stderr
+		quiet: bool,
+	},
+	/// Run Rust code
+	Call {
+		description: String,
+		/// Equivalent `/bin/sh` command, for `ssmake export`
+		shell: Option<String>,
+		f: CallFn<'a>,
+	},
+}
+
+/// Why an action failed
+#[derive(Debug, Clone)]
+pub struct Failure {
+	pub message: String,
+	/// Exit code of the program, if the action was a command that ran
+	pub exit_code: Option<i32>,
+}
+
+impl From<String> for Failure {
+	fn from(message: String) -> Self {
+		Self { message, exit_code: None }
+	}
+}
+
+impl std::fmt::Display for Failure {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&self.message)
+	}
+}
+
+impl Action<'_> {
+	/// Human readable form of the action
+	pub fn describe(&self) -> String {
+		match self {
+			Action::Command { program, args, stdout, .. } => {
+				let mut line = std::iter::once(program)
+					.chain(args)
+					.cloned()
+					.collect::<Vec<String>>()
+					.join(" ");
+				if let Some(stdout) = stdout {
+					line += &format!(" > {}", stdout.display());
+				}
+				line
+			}
+			Action::Call { description, .. } => description.clone(),
+		}
+	}
+
+	/// The action as a `/bin/sh` command, if it has one
+	pub fn shell(&self) -> Option<String> {
+		match self {
+			Action::Command { program, args, stdout, quiet } => {
+				let mut line = shell_command(program, args);
+				if let Some(stdout) = stdout {
+					line += &format!(" > {}", quote(&stdout.display().to_string()));
+				}
+				if *quiet {
+					line += " 2> /dev/null";
+				}
+				Some(line)
+			}
+			Action::Call { shell, .. } => shell.clone(),
+		}
+	}
+
+	/// Run the action, collecting what the program prints into `output`
+	/// rather than printing it
+	fn run(&self, output: &mut String) -> Result<(), Failure> {
+		match self {
+			Action::Command { program, args, stdout, quiet } => {
+				let mut expr = cmd(program, args);
+				if let Some(stdout) = stdout {
+					expr = expr.stdout_path(stdout);
+				}
+				if *quiet {
+					run_captured(expr, program, &mut String::new())
+				} else {
+					run_captured(expr, program, output)
+				}
+			}
+			Action::Call { f, .. } => Ok(f(output)?),
+		}
+	}
+}
+
+/// Quote `arg` for `/bin/sh` if needed
+pub fn quote(arg: &str) -> String {
+	let safe = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./-_".contains(c);
+	if !arg.is_empty() && arg.chars().all(safe) {
+		arg.to_owned()
+	} else {
+		format!("'{}'", arg.replace('\'', r"'\''"))
+	}
+}
+
+/// `program` and `args` as a `/bin/sh` command line
+pub fn shell_command<S: AsRef<str>>(program: &str, args: &[S]) -> String {
+	std::iter::once(quote(program))
+		.chain(args.iter().map(|arg| quote(arg.as_ref())))
+		.collect::<Vec<String>>()
+		.join(" ")
+}
+
+/// Run `expr`, appending its stdout (unless redirected) and stderr to
+/// `output`
+pub fn run_captured(expr: duct::Expression, program: &str, output: &mut String) -> Result<(), Failure> {
+	let result = expr.stdout_capture()
+		.stderr_capture()
+		.unchecked()
+		.run()
+		.map_err(|e| format!("unable to run '{program}': {e}"))?;
+	output.push_str(&String::from_utf8_lossy(&result.stdout));
+	output.push_str(&String::from_utf8_lossy(&result.stderr));
+	if result.status.success() {
+		Ok(())
+	} else {
+		Err(Failure {
+			message: format!("'{program}' failed ({})", result.status),
+			exit_code: result.status.code(),
+		})
+	}
+}
+
+/// A target in the build graph. Nodes are ordered by matching their inputs
+/// against the outputs of other nodes.
+pub struct Node<'a> {
+	pub name: String,
+	pub inputs: Vec<PathBuf>,
+	pub outputs: Vec<PathBuf>,
+	/// Make-style dependency file written by the actions, listing additional
+	/// inputs (e.g. headers)
+	pub depfile: Option<PathBuf>,
+	pub actions: Vec<Action<'a>>,
+}
+
+impl<'a> Node<'a> {
+	pub fn new<S: Into<String>>(name: S) -> Self {
+		Self {
+			name: name.into(),
+			inputs: vec![],
+			outputs: vec![],
+			depfile: None,
+			actions: vec![],
+		}
+	}
+
+	pub fn input<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.inputs.push(path.into());
+		self
+	}
+
+	pub fn inputs<I: IntoIterator<Item = PathBuf>>(mut self, paths: I) -> Self {
+		self.inputs.extend(paths);
+		self
+	}
+
+	pub fn output<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.outputs.push(path.into());
+		self
+	}
+
+	pub fn depfile<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.depfile = Some(path.into());
+		self
+	}
+
+	pub fn command<S: Into<String>>(self, program: S, args: Vec<String>) -> Self {
+		self.action(Action::Command { program: program.into(), args, stdout: None, quiet: false })
+	}
+
+	pub fn action(mut self, action: Action<'a>) -> Self {
+		self.actions.push(action);
+		self
+	}
+
+	pub fn call<S, F>(self, description: S, f: F) -> Self
+	where
+		S: Into<String>,
+		F: Fn() -> Result<(), String> + 'a,
+	{
+		self.call_with_output(description, move |_| f())
+	}
+
+	/// Like [`Node::call`], for code whose output should be reported
+	pub fn call_with_output<S, F>(self, description: S, f: F) -> Self
+	where
+		S: Into<String>,
+		F: Fn(&mut String) -> Result<(), String> + 'a,
+	{
+		self.action(Action::Call { description: description.into(), shell: None, f: Box::new(f) })
+	}
+
+	/// Give the last [`Node::call`] a `/bin/sh` equivalent, so it can be
+	/// exported
+	pub fn shell<S: Into<String>>(mut self, command: S) -> Self {
+		match self.actions.last_mut() {
+			Some(Action::Call { shell, .. }) => *shell = Some(command.into()),
+			_ => panic!("{}: shell() only applies to a call", self.name),
+		}
+		self
+	}
+
+	/// Declared inputs plus the ones listed in the dependency file
+	pub fn all_inputs(&self) -> Vec<PathBuf> {
+		let mut inputs = self.inputs.clone();
+		if let Some(depfile) = &self.depfile {
+			inputs.extend(read_depfile(depfile));
+		}
+		inputs
+	}
+}
+
+/// Decides whether a node needs to run
+pub trait Policy {
+	/// Why `node` needs to run, or `None` if it's up to date
+	fn stale_reason(&self, node: &Node) -> Option<String>;
+
+	/// Called before the actions of `node` run
+	fn building(&self, _node: &Node) {}
+
+	/// Called after every action of `node` succeeded
+	fn built(&self, _node: &Node) {}
+}
+
+/// A node is stale when an output or input is missing, or an input is newer
+/// than its oldest output. Nodes without outputs always run.
+pub struct Mtime;
+
+impl Policy for Mtime {
+	fn stale_reason(&self, node: &Node) -> Option<String> {
+		if node.outputs.is_empty() {
+			return Some("always runs (no outputs)".into());
+		}
+
+		let mut oldest: Option<(&PathBuf, SystemTime)> = None;
+		for output in node.outputs.iter() {
+			let Ok(date) = std::fs::metadata(output).and_then(|md| md.modified()) else {
+				return Some(format!("'{}' is missing", output.display()));
+			};
+			if oldest.is_none_or(|(_, oldest_date)| date < oldest_date) {
+				oldest = Some((output, date));
+			}
+		}
+		let (output, oldest_date) = oldest?;
+
+		node.all_inputs().iter().find_map(|input| match get_mod_date(input) {
+			None => Some(format!("'{}' is missing", input.display())),
+			Some(date) if date > oldest_date => Some(format!("'{}' is newer than '{}'", input.display(), output.display())),
+			Some(_) => None,
+		})
+	}
+}
+
+/// How [`Graph::run`] runs stale nodes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Options {
+	/// Report the actions of stale nodes instead of running them
+	pub dry_run: bool,
+	/// Keep running the nodes that don't depend on a failed one, failing at
+	/// the end
+	pub keep_going: bool,
+}
+
+/// Receives progress while the graph runs
+pub trait Reporter {
+	/// `node` is step `step` of `total` and is about to run because of `reason`
+	fn started(&self, _step: usize, _total: usize, _node: &Node, _reason: &str) {}
+
+	/// `action` of `node` is about to run (or would have, on a dry run)
+	fn action(&self, _node: &Node, _action: &Action) {}
+
+	/// What an action of `node` printed
+	fn output(&self, _node: &Node, _output: &str) {}
+
+	fn finished(&self, _node: &Node, _duration: Duration, _result: &Result<(), Failure>) {}
+
+	/// Every node ran, or one failed
+	fn done(&self, _ran: usize, _duration: Duration, _result: &Result<usize, String>) {}
+}
+
+/// Forwards everything to several reporters
+pub struct Reporters<'r>(pub Vec<&'r dyn Reporter>);
+
+impl Reporter for Reporters<'_> {
+	fn started(&self, step: usize, total: usize, node: &Node, reason: &str) {
+		self.0.iter().for_each(|r| r.started(step, total, node, reason));
+	}
+
+	fn action(&self, node: &Node, action: &Action) {
+		self.0.iter().for_each(|r| r.action(node, action));
+	}
+
+	fn output(&self, node: &Node, output: &str) {
+		self.0.iter().for_each(|r| r.output(node, output));
+	}
+
+	fn finished(&self, node: &Node, duration: Duration, result: &Result<(), Failure>) {
+		self.0.iter().for_each(|r| r.finished(node, duration, result));
+	}
+
+	fn done(&self, ran: usize, duration: Duration, result: &Result<usize, String>) {
+		self.0.iter().for_each(|r| r.done(ran, duration, result));
+	}
+}
+
+/// `None` when `a` is missing
+pub fn get_mod_date<P: AsRef<Path>>(a: P) -> Option<SystemTime> {
+	std::fs::metadata(a.as_ref())
+		.and_then(|data| data.modified())
+		.ok()
+}
+
+/// Read the prerequisites from a make-style dependency file written by `-MD`
+pub fn read_depfile<P: AsRef<Path>>(path: P) -> Vec<PathBuf> {
+	std::fs::read_to_string(path.as_ref())
+		.map(|contents| parse_depfile(&contents))
+		.unwrap_or_default()
+}
+
+fn parse_depfile(contents: &str) -> Vec<PathBuf> {
+	let Some((_, prerequisites)) = contents.split_once(": ") else {
+		return vec![];
+	};
+
+	let mut deps = vec![];
+	let mut current = String::new();
+	let mut chars = prerequisites.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'\\' if chars.peek() == Some(&' ') => {
+				current.push(' ');
+				chars.next();
+			}
+			'\\' if chars.peek() == Some(&'\n') => {
+				chars.next();
+			}
+			c if c.is_whitespace() => if !current.is_empty() {
+				deps.push(PathBuf::from(std::mem::take(&mut current)));
+			}
+			c => current.push(c),
+		}
+	}
+	if !current.is_empty() {
+		deps.push(PathBuf::from(current));
+	}
+	deps
+}
+
+#[derive(Default)]
+pub struct Graph<'a> {
+	nodes: Vec<Node<'a>>,
+}
+
+impl<'a> Graph<'a> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn add(&mut self, node: Node<'a>) {
+		self.nodes.push(node);
+	}
+
+	/// Indices of the nodes in dependency order. Independent nodes keep the
+	/// order they were added in.
+	pub fn order(&self) -> Result<Vec<usize>, String> {
+		let mut producers = HashMap::<&Path, usize>::new();
+		for (i, node) in self.nodes.iter().enumerate() {
+			for output in node.outputs.iter() {
+				if let Some(other) = producers.insert(output, i) {
+					return Err(format!("'{}' is an output of both '{}' and '{}'",
+						output.display(), self.nodes[other].name, node.name));
+				}
+			}
+		}
+
+		let mut dependents = vec![vec![]; self.nodes.len()];
+		let mut pending = vec![0; self.nodes.len()];
+		for (i, node) in self.nodes.iter().enumerate() {
+			let deps: BTreeSet<usize> = node.inputs.iter()
+				.flat_map(|input| producers.get(input.as_path()).copied())
+				.filter(|&dep| dep != i)
+				.collect();
+			pending[i] = deps.len();
+			for dep in deps {
+				dependents[dep].push(i);
+			}
+		}
+
+		let mut ready: BTreeSet<usize> = (0..self.nodes.len())
+			.filter(|&i| pending[i] == 0)
+			.collect();
+		let mut order = Vec::with_capacity(self.nodes.len());
+		while let Some(i) = ready.pop_first() {
+			order.push(i);
+			for &dependent in dependents[i].iter() {
+				pending[dependent] -= 1;
+				if pending[dependent] == 0 {
+					ready.insert(dependent);
+				}
+			}
+		}
+
+		if order.len() != self.nodes.len() {
+			let cycle: Vec<&str> = (0..self.nodes.len())
+				.filter(|&i| pending[i] > 0)
+				.map(|i| self.nodes[i].name.as_str())
+				.collect();
+			return Err(format!("dependency cycle between {}", cycle.join(", ")));
+		}
+
+		Ok(order)
+	}
+
+	/// Stale nodes in dependency order with the reason they need to run. A
+	/// node whose inputs are rebuilt is stale too, even though its inputs
+	/// haven't changed yet.
+	fn plan(&self, policy: &dyn Policy) -> Result<Vec<(usize, String)>, String> {
+		let mut rebuilt = HashSet::<&Path>::new();
+		let mut plan = vec![];
+		for i in self.order()? {
+			let node = &self.nodes[i];
+			let reason = node.inputs.iter()
+				.find(|input| rebuilt.contains(input.as_path()))
+				.map(|input| format!("'{}' will be rebuilt", input.display()))
+				.or_else(|| policy.stale_reason(node));
+			match reason {
+				Some(reason) => {
+					rebuilt.extend(node.outputs.iter().map(PathBuf::as_path));
+					plan.push((i, reason));
+				}
+				None => trace!("up to date: {}", node.name),
+			}
+		}
+		Ok(plan)
+	}
+
+	/// Run every stale node in dependency order, stopping at the first
+	/// failure unless `options.keep_going` is set. Returns the number of
+	/// nodes that ran (or would have run).
+	pub fn run(&self, policy: &dyn Policy, options: Options, reporter: &dyn Reporter) -> Result<usize, String> {
+		let start = Instant::now();
+		let result = self.run_plan(policy, options, reporter);
+		reporter.done(result.as_ref().map_or(0, |ran| *ran), start.elapsed(), &result);
+		result
+	}
+
+	fn run_plan(&self, policy: &dyn Policy, options: Options, reporter: &dyn Reporter) -> Result<usize, String> {
+		let plan = self.plan(policy)?;
+		let total = plan.len();
+
+		let mut ran = 0;
+		let mut failed = vec![];
+		let mut failed_outputs = HashSet::<&Path>::new();
+		for (step, (i, planned_reason)) in plan.into_iter().enumerate() {
+			let node = &self.nodes[i];
+			if node.inputs.iter().any(|input| failed_outputs.contains(input.as_path())) {
+				trace!("skipping {}, an input failed to build", node.name);
+				failed_outputs.extend(node.outputs.iter().map(PathBuf::as_path));
+				continue;
+			}
+			if options.dry_run {
+				reporter.started(step + 1, total, node, &planned_reason);
+				for action in node.actions.iter() {
+					reporter.action(node, action);
+				}
+				ran += 1;
+				continue;
+			}
+
+			// The rebuilt inputs may have come out identical
+			let Some(reason) = policy.stale_reason(node) else {
+				trace!("up to date: {}", node.name);
+				continue;
+			};
+
+			trace!("building {}", node.name);
+			policy.building(node);
+			reporter.started(step + 1, total, node, &reason);
+			let start = Instant::now();
+			let result = node.actions.iter().try_for_each(|action| {
+				reporter.action(node, action);
+				debug!("  '{}'", action.describe());
+				let mut output = String::new();
+				let result = action.run(&mut output);
+				if !output.is_empty() {
+					reporter.output(node, &output);
+				}
+				result
+			});
+			reporter.finished(node, start.elapsed(), &result);
+			match result {
+				Ok(()) => policy.built(node),
+				Err(e) if options.keep_going => {
+					debug!("{}: {e}", node.name);
+					failed.push(node.name.as_str());
+					failed_outputs.extend(node.outputs.iter().map(PathBuf::as_path));
+				}
+				Err(e) => return Err(format!("{}: {e}", node.name)),
+			}
+			ran += 1;
+		}
+		if !failed.is_empty() {
+			return Err(format!("{} of {ran} steps failed: {}", failed.len(), failed.join(", ")));
+		}
+		Ok(ran)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_depfiles() {
+		// As written by 'sh2eb-elf-gcc -MD' for a source in a directory with a space
+		let depfile = "\
+/tmp/my\\ game/build/@tmp@my\\ game@main.o: /tmp/my\\ game/main.c \\
+ /opt/sh2eb-elf/sh2eb-elf/include/yaul/yaul.h \\
+ /tmp/my\\ game/include/sprite\\ data.h
+";
+		assert_eq!(parse_depfile(depfile), [
+			PathBuf::from("/tmp/my game/main.c"),
+			PathBuf::from("/opt/sh2eb-elf/sh2eb-elf/include/yaul/yaul.h"),
+			PathBuf::from("/tmp/my game/include/sprite data.h"),
+		]);
+	}
+
+	struct Quiet;
+
+	impl Reporter for Quiet {}
+
+	#[test]
+	fn keeps_going_past_failures() {
+		let ran = std::cell::RefCell::new(vec![]);
+		let run = |options: Options| {
+			ran.borrow_mut().clear();
+			let mut graph = Graph::new();
+			graph.add(Node::new("a")
+				.output("/nonexistent/a.o")
+				.call("fail", || Err("failed".into())));
+			graph.add(Node::new("b")
+				.input("/nonexistent/a.o")
+				.output("/nonexistent/b.o")
+				.call("record", || {
+					ran.borrow_mut().push("b");
+					Ok(())
+				}));
+			graph.add(Node::new("c")
+				.call("record", || {
+					ran.borrow_mut().push("c");
+					Ok(())
+				}));
+			graph.run(&Mtime, options, &Quiet)
+		};
+
+		assert_eq!(run(Options::default()), Err("a: failed".into()));
+		assert!(ran.borrow().is_empty());
+
+		// 'b' depends on the failed 'a', 'c' doesn't
+		assert_eq!(run(Options { keep_going: true, ..Options::default() }), Err("1 of 2 steps failed: a".into()));
+		assert_eq!(*ran.borrow(), ["c"]);
+	}
+
+	#[test]
+	fn ignores_files_without_prerequisites() {
+		assert!(parse_depfile("").is_empty());
+		assert!(read_depfile("/nonexistent/main.d").is_empty());
+	}
+
+	#[test]
+	fn exports_actions_as_shell_commands() {
+		let nm = Action::Command {
+			program: "sh2eb-elf-nm".into(),
+			args: vec!["-S".into(), "my game.elf".into()],
+			stdout: Some("my game.sym".into()),
+			quiet: true,
+		};
+		assert_eq!(nm.shell().as_deref(), Some("sh2eb-elf-nm -S 'my game.elf' > 'my game.sym' 2> /dev/null"));
+
+		let node = Node::new("report")
+			.call("generate size report", || Ok(()))
+			.call("make-ip", || Ok(()))
+			.shell("make-ip t.bin 'it'\\''s'");
+		let shells: Vec<Option<String>> = node.actions.iter().map(Action::shell).collect();
+		assert_eq!(shells, [None, Some("make-ip t.bin 'it'\\''s'".into())]);
+	}
+}
@@ -0,0 +1,87 @@
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::diagnostic;
+use crate::graph::{Action, Failure, Node, Reporter};
+
+/// Line-delimited JSON build events on stdout (`--message-format=json`)
+pub struct Json;
+
+impl Json {
+	fn emit(&self, event: Value) {
+		println!("{event}");
+	}
+}
+
+impl Reporter for Json {
+	fn started(&self, step: usize, total: usize, node: &Node, reason: &str) {
+		self.emit(json!({
+			"reason": "target-started",
+			"target": node.name,
+			"step": step,
+			"total": total,
+			"explanation": reason,
+		}));
+	}
+
+	fn action(&self, node: &Node, action: &Action) {
+		self.emit(json!({
+			"reason": "command",
+			"target": node.name,
+			"command": action.describe(),
+		}));
+	}
+
+	fn output(&self, node: &Node, output: &str) {
+		for diagnostic in diagnostic::parse(output) {
+			self.emit(json!({
+				"reason": "diagnostic",
+				"target": node.name,
+				"diagnostic": diagnostic,
+			}));
+		}
+	}
+
+	fn finished(&self, node: &Node, duration: Duration, result: &Result<(), Failure>) {
+		// Failures without a program's exit code (killed, or not a command)
+		// are reported as 1
+		let exit_code = match result {
+			Ok(()) => 0,
+			Err(e) => e.exit_code.unwrap_or(1),
+		};
+		self.emit(json!({
+			"reason": "target-finished",
+			"target": node.name,
+			"duration": duration.as_secs_f64(),
+			"success": result.is_ok(),
+			"exit_code": exit_code,
+			"error": result.as_ref().err().map(|e| e.message.clone()),
+		}));
+
+		if result.is_ok() {
+			for output in node.outputs.iter() {
+				let Ok(md) = std::fs::metadata(output) else {
+					continue;
+				};
+				self.emit(json!({
+					"reason": "artifact",
+					"target": node.name,
+					"path": output,
+					"size": md.len(),
+				}));
+			}
+		}
+	}
+
+	fn done(&self, ran: usize, duration: Duration, result: &Result<usize, String>) {
+		self.emit(json!({
+			"reason": "build-finished",
+			"success": result.is_ok(),
+			"steps": ran,
+			"duration": duration.as_secs_f64(),
+			"error": result.as_ref().err(),
+		}));
+	}
+}
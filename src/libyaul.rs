@@ -0,0 +1,199 @@
+
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use toml::{Table, Value};
+use tracing::trace;
+
+use crate::graph;
+use crate::toolchain::Toolchain;
+
+/// Value of `libyaul.variant`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+	Release,
+	Debug,
+}
+
+impl Variant {
+	fn name(self) -> &'static str {
+		match self {
+			Self::Release => "release",
+			Self::Debug => "debug",
+		}
+	}
+}
+
+/// Value of `libyaul.malloc` (`YAUL_OPTION_MALLOC_IMPL`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malloc {
+	/// Two-Level Segregated Fit
+	Tlsf,
+	/// No memory allocator
+	None,
+}
+
+impl Malloc {
+	fn name(self) -> &'static str {
+		match self {
+			Self::Tlsf => "tlsf",
+			Self::None => "none",
+		}
+	}
+}
+
+/// libyaul built from a source tree (`[libyaul]`) instead of the one installed
+/// in the tool-chain
+#[derive(Debug, Clone)]
+pub struct Libyaul {
+	/// Root of the Yaul source tree
+	pub source: PathBuf,
+	pub variant: Variant,
+	pub malloc: Malloc,
+	/// Install root the build is staged into, in the build directory. Each
+	/// variant and allocator gets its own, so switching doesn't rebuild.
+	pub root: PathBuf,
+}
+
+impl Libyaul {
+	/// `None` unless `libyaul.source` is set
+	pub fn from_config(config: &Table, build_path: &Path) -> Result<Option<Self>, String> {
+		let Some(libyaul) = config.get("libyaul") else {
+			return Ok(None);
+		};
+		let Some(source) = libyaul.get("source").and_then(Value::as_str).map(PathBuf::from) else {
+			return Ok(None);
+		};
+		let variant = match libyaul.get("variant") {
+			Some(Value::String(v)) if v == "release" => Variant::Release,
+			Some(Value::String(v)) if v == "debug" => Variant::Debug,
+			Some(v) => return Err(format!("invalid libyaul.variant = {v} (expected \"release\" or \"debug\")")),
+			None => Variant::Release,
+		};
+		let malloc = match libyaul.get("malloc") {
+			Some(Value::String(v)) if v == "tlsf" => Malloc::Tlsf,
+			Some(Value::String(v)) if v == "none" => Malloc::None,
+			Some(v) => return Err(format!("invalid libyaul.malloc = {v} (expected \"tlsf\" or \"none\")")),
+			None => Malloc::Tlsf,
+		};
+		let root = std::path::absolute(build_path)
+			.unwrap_or_else(|_| build_path.to_owned())
+			.join("libyaul")
+			.join(format!("{}-{}", variant.name(), malloc.name()));
+
+		trace!("libyaul config");
+		trace!("  source  = '{}'", source.display());
+		trace!("  variant = {variant:?}");
+		trace!("  malloc  = {malloc:?}");
+		trace!("  root    = '{}'", root.display());
+
+		Ok(Some(Self { source, variant, malloc, root }))
+	}
+
+	/// Headers, as installed under `{root}/{arch}/include/yaul`
+	pub fn include_dir(&self, toolchain: &Toolchain) -> PathBuf {
+		self.root.join(&toolchain.arch_prefix).join("include/yaul")
+	}
+
+	/// Library directory holding `libyaul.a` and the specs files, passed to
+	/// GCC with `-B`
+	pub fn lib_dir(&self, toolchain: &Toolchain) -> PathBuf {
+		self.root.join(&toolchain.arch_prefix).join("lib")
+	}
+
+	pub fn library(&self, toolchain: &Toolchain) -> PathBuf {
+		self.lib_dir(toolchain).join("libyaul.a")
+	}
+
+	/// Every file of the source tree, skipping hidden directories like `.git`
+	pub fn sources(&self) -> Vec<PathBuf> {
+		fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+			let Ok(entries) = std::fs::read_dir(dir) else {
+				return;
+			};
+			for entry in entries.flatten() {
+				if entry.file_name().to_string_lossy().starts_with('.') {
+					continue;
+				}
+				match entry.file_type() {
+					Ok(kind) if kind.is_dir() => walk(&entry.path(), files),
+					_ => files.push(entry.path()),
+				}
+			}
+		}
+		let mut files = vec![];
+		walk(&self.source, &mut files);
+		files.sort();
+		files
+	}
+
+	/// Variables Yaul's Makefiles read, pointing the install at the staged root
+	pub fn environment(&self, toolchain: &Toolchain) -> Vec<(&'static str, String)> {
+		let malloc = match self.malloc {
+			Malloc::Tlsf => "tlsf",
+			Malloc::None => "",
+		};
+		// Objects go next to the staged root, in '{build}/libyaul/obj/{variant}-{malloc}'
+		let name = self.root.file_name().unwrap_or_default().to_string_lossy().into_owned();
+		let objects = self.root.with_file_name("obj");
+		vec![
+			("YAUL_INSTALL_ROOT", self.root.display().to_string()),
+			("YAUL_ARCH_SH_PREFIX", toolchain.arch_prefix.clone()),
+			("YAUL_PROG_SH_PREFIX", toolchain.prog_prefix.clone()),
+			("YAUL_BUILD_ROOT", objects.display().to_string()),
+			("YAUL_BUILD", name),
+			("YAUL_OPTION_MALLOC_IMPL", malloc.to_owned()),
+			("SILENT", "1".to_owned()),
+			("NOCOLOR", "1".to_owned()),
+		]
+	}
+
+	/// `make` targets building and installing the variant
+	pub fn targets(&self) -> [String; 2] {
+		let variant = self.variant.name();
+		[variant.to_owned(), format!("install-{variant}")]
+	}
+
+	/// The staged root has no compilers of its own, so its `bin` points at the
+	/// tool-chain's
+	pub fn stage(&self, toolchain: &Toolchain) -> Result<(), String> {
+		std::fs::create_dir_all(&self.root)
+			.map_err(|e| format!("unable to create '{}': {e}", self.root.display()))?;
+		let bin = self.root.join("bin");
+		if std::fs::symlink_metadata(&bin).is_err() {
+			std::os::unix::fs::symlink(Path::new(&toolchain.install_root).join("bin"), &bin)
+				.map_err(|e| format!("unable to link '{}': {e}", bin.display()))?;
+		}
+		Ok(())
+	}
+
+	/// Build and install the variant into the staged root
+	pub fn make(&self, toolchain: &Toolchain, output: &mut String) -> Result<(), String> {
+		self.stage(toolchain)?;
+		for target in self.targets() {
+			let mut expr = cmd!("make", "-C", &self.source, &target);
+			for (name, value) in self.environment(toolchain) {
+				expr = expr.env(name, value);
+			}
+			graph::run_captured(expr, "make", output)
+				.map_err(|e| e.message)?;
+		}
+		Ok(())
+	}
+
+	/// [`Libyaul::make`] as a `/bin/sh` command
+	pub fn make_shell(&self, toolchain: &Toolchain) -> String {
+		let root = self.root.display().to_string();
+		let bin = self.root.join("bin").display().to_string();
+		let environment: Vec<String> = self.environment(toolchain).into_iter()
+			.map(|(name, value)| format!("{name}={}", graph::quote(&value)))
+			.collect();
+		let make_args = [&["-C".to_owned(), self.source.display().to_string()][..], &self.targets()].concat();
+		[
+			graph::shell_command("mkdir", &["-p", &root]),
+			format!("([ -e {} ] || {})", graph::quote(&bin),
+				graph::shell_command("ln", &["-s", &format!("{}/bin", toolchain.install_root), &bin])),
+			format!("{} {}", environment.join(" "), graph::shell_command("make", &make_args)),
+		].join(" && ")
+	}
+}
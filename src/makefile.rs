@@ -0,0 +1,435 @@
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use toml::Value;
+use tracing::{info, warn};
+
+/// Variables of Yaul's Makefile template that have a `config.toml`
+/// equivalent, as (variable, section, key)
+const VARIABLES: [(&str, &str, &str); 16] = [
+	("SH_BUILD_DIR", "dirs", "build"),
+	("SH_OUTPUT_DIR", "dirs", "output"),
+	("IMAGE_DIRECTORY", "dirs", "image"),
+	("AUDIO_TRACKS_DIRECTORY", "dirs", "audio"),
+	("SH_PROGRAM", "sh", "program"),
+	("SH_SRCS", "sh", "srcs"),
+	("SH_LDFLAGS", "sh", "symbols"),
+	("SH_DEFSYMS", "sh", "symbols"),
+	("IP_VERSION", "ip", "version"),
+	("IP_RELEASE_DATE", "ip", "release-date"),
+	("IP_AREAS", "ip", "areas"),
+	("IP_PERIPHERALS", "ip", "peripherals"),
+	("IP_TITLE", "ip", "title"),
+	("IP_MASTER_STACK_ADDR", "ip", "main-stack-addr"),
+	("IP_SLAVE_STACK_ADDR", "ip", "sub-stack-addr"),
+	("IP_1ST_READ_ADDR", "ip", "1st-read-addr"),
+];
+
+/// Defaults `ss.mk` gives the directory variables
+const DIR_DEFAULTS: [(&str, &str); 4] = [
+	("SH_BUILD_DIR", "build"),
+	("SH_OUTPUT_DIR", "."),
+	("IMAGE_DIRECTORY", "cd"),
+	("AUDIO_TRACKS_DIRECTORY", "audio-tracks"),
+];
+
+/// Variables that end up as lists rather than a single value
+const LISTS: [&str; 4] = ["SH_SRCS", "SH_LDFLAGS", "SH_DEFSYMS", "BUILTIN_ASSETS"];
+
+/// Include lines of Yaul's template that ssmake itself replaces
+const YAUL_INCLUDES: [&str; 2] = ["build.pre.mk", "build.post.mk"];
+
+/// A Makefile line that couldn't be translated
+#[derive(Debug, Clone)]
+pub struct Untranslated {
+	pub line: usize,
+	pub text: String,
+	pub reason: String,
+}
+
+/// Variables read from a Makefile
+#[derive(Debug, Default)]
+pub struct Makefile {
+	pub variables: BTreeMap<String, Vec<String>>,
+	/// Variables expanded into others, so they don't need an equivalent
+	pub referenced: BTreeSet<String>,
+	pub untranslated: Vec<Untranslated>,
+}
+
+impl Makefile {
+	/// Read the simple assignments (`=`, `:=`, `?=`, `+=`) of a Makefile.
+	/// Everything else (rules, conditionals, functions) is recorded as
+	/// untranslated.
+	pub fn parse(text: &str) -> Self {
+		let mut makefile = Self::default();
+		for (line, logical) in logical_lines(text) {
+			let stripped = strip_comment(&logical);
+			let stripped = stripped.trim();
+			if stripped.is_empty() {
+				continue;
+			}
+
+			let note = |reason: &str| Untranslated {
+				line,
+				text: stripped.to_owned(),
+				reason: reason.to_owned(),
+			};
+
+			if logical.starts_with('\t') {
+				makefile.untranslated.push(note("recipe outside of a rule"));
+				continue;
+			}
+
+			let keyword = stripped.split_whitespace().next().unwrap_or_default();
+			match keyword {
+				"include" | "-include" | "sinclude"
+					if YAUL_INCLUDES.iter().any(|mk| stripped.ends_with(mk)) => continue,
+				"include" | "-include" | "sinclude" => {
+					makefile.untranslated.push(note("included Makefiles aren't imported"));
+					continue;
+				}
+				"ifeq" | "ifneq" | "ifdef" | "ifndef" | "else" | "endif" => {
+					makefile.untranslated.push(note("conditionals aren't supported"));
+					continue;
+				}
+				"define" | "endef" => {
+					makefile.untranslated.push(note("multi-line variables aren't supported"));
+					continue;
+				}
+				_ => {}
+			}
+
+			let Some((name, op, value)) = split_assignment(stripped) else {
+				makefile.untranslated.push(note(if stripped.contains(':') { "rules aren't supported" } else { "directives aren't supported" }));
+				continue;
+			};
+			let name = ["export ", "override "].iter()
+				.fold(name, |name, prefix| name.strip_prefix(prefix).unwrap_or(name))
+				.trim();
+
+			let value = match makefile.expand(value) {
+				Ok(value) => value,
+				Err(reason) => {
+					makefile.untranslated.push(note(&reason));
+					continue;
+				}
+			};
+			let words: Vec<String> = value.split_whitespace().map(str::to_owned).collect();
+			match op {
+				"+=" => makefile.variables.entry(name.to_owned()).or_default().extend(words),
+				"?=" => {
+					makefile.variables.entry(name.to_owned()).or_insert(words);
+				}
+				_ => {
+					makefile.variables.insert(name.to_owned(), words);
+				}
+			}
+		}
+		makefile
+	}
+
+	/// Replace `$(VAR)` and `${VAR}` with variables assigned earlier
+	fn expand(&mut self, value: &str) -> Result<String, String> {
+		let mut out = String::with_capacity(value.len());
+		let mut rest = value;
+		while let Some(start) = rest.find('$') {
+			out.push_str(&rest[..start]);
+			rest = &rest[start + 1..];
+			let close = match rest.chars().next() {
+				Some('(') => ')',
+				Some('{') => '}',
+				Some('$') => {
+					out.push('$');
+					rest = &rest[1..];
+					continue;
+				}
+				_ => return Err("automatic or single-letter variables aren't supported".into()),
+			};
+			let end = rest.find(close)
+				.ok_or_else(|| "unterminated variable reference".to_string())?;
+			let name = &rest[1..end];
+			if name.contains(char::is_whitespace) || name.contains('$') {
+				return Err(format!("function '$({name})' isn't supported"));
+			}
+			let words = self.variables.get(name)
+				.ok_or_else(|| format!("'{name}' isn't defined in this Makefile"))?;
+			out.push_str(&words.join(" "));
+			self.referenced.insert(name.to_owned());
+			rest = &rest[end + 1..];
+		}
+		out.push_str(rest);
+		Ok(out)
+	}
+
+	/// Variables with no `config.toml` equivalent that weren't used by others
+	fn unknown(&self) -> impl Iterator<Item = &String> {
+		self.variables.keys()
+			.filter(|name| !is_known(name) && !self.referenced.contains(*name))
+	}
+
+	/// The equivalent `config.toml`. Variables without an equivalent are
+	/// listed as comments at the top along with the untranslated lines.
+	pub fn to_config(&self) -> String {
+		let get = |name: &str| self.variables.get(name).filter(|words| !words.is_empty());
+		let string = |words: &Vec<String>| Value::String(words.join(" ")).to_string();
+		let list = |words: &[String]| format!("[{}]", words.iter()
+			.map(|word| Value::String(word.clone()).to_string())
+			.collect::<Vec<String>>()
+			.join(", "));
+
+		let mut notes: Vec<String> = self.untranslated.iter()
+			.map(|u| format!("line {}: {} ({})", u.line, u.text, u.reason))
+			.collect();
+		notes.extend(self.unknown()
+			.map(|name| format!("{name} has no config.toml equivalent")));
+
+		let mut out = String::new();
+		if !notes.is_empty() {
+			out.push_str("# Not translated from the Makefile:\n");
+			for note in notes {
+				out.push_str(&format!("#   {note}\n"));
+			}
+			out.push('\n');
+		}
+
+		// BUILTIN_ASSETS paths are relative to the project, so the asset
+		// directory is the project itself
+		let assets: Vec<(&str, &str)> = get("BUILTIN_ASSETS")
+			.into_iter()
+			.flatten()
+			.flat_map(|asset| asset.split_once(';'))
+			.collect();
+		out.push_str("assets = [\n");
+		for (file, name) in assets.iter() {
+			out.push_str(&format!("\t{{ file = {}, name = {} }},\n",
+				Value::String(file.to_string()),
+				Value::String(name.to_string())));
+		}
+		out.push_str("]\n");
+
+		let mut section = "";
+		for (variable, table, key) in VARIABLES {
+			if table != section {
+				section = table;
+				out.push_str(&format!("\n[{table}]\n"));
+				if table == "dirs" {
+					out.push_str(&format!("assets = {}\n", Value::String(".".into())));
+				}
+			}
+
+			let value = match (variable, key) {
+				("SH_LDFLAGS", _) => continue,
+				("SH_DEFSYMS", _) => {
+					let symbols: Vec<String> = get("SH_LDFLAGS").into_iter()
+						.chain(get("SH_DEFSYMS"))
+						.flatten()
+						.cloned()
+						.collect();
+					list(&symbols)
+				}
+				(variable, _) if LISTS.contains(&variable) => {
+					list(get(variable).map(Vec::as_slice).unwrap_or_default())
+				}
+				(variable, "release-date") | (variable, "main-stack-addr")
+				| (variable, "sub-stack-addr") | (variable, "1st-read-addr") => {
+					match get(variable) {
+						Some(words) if words.len() == 1 && parse_integer(&words[0]).is_some() => words[0].clone(),
+						Some(words) => string(words),
+						None => continue,
+					}
+				}
+				(variable, _) => match get(variable) {
+					Some(words) => string(words),
+					None => match DIR_DEFAULTS.iter().find(|(name, _)| *name == variable) {
+						Some((_, dir)) => Value::String(dir.to_string()).to_string(),
+						None => continue,
+					},
+				},
+			};
+			out.push_str(&format!("{key} = {value}\n"));
+		}
+
+		// Added last since "auto" isn't a Makefile value
+		if let Some(words) = get("IP_1ST_READ_SIZE") {
+			let value = match words.as_slice() {
+				[size] if parse_integer(size).is_some() => size.clone(),
+				_ => string(words),
+			};
+			out.push_str(&format!("1st-read-size = {value}\n"));
+		}
+		out
+	}
+}
+
+fn is_known(name: &str) -> bool {
+	VARIABLES.iter().any(|(variable, ..)| *variable == name)
+		|| ["BUILTIN_ASSETS", "IP_1ST_READ_SIZE"].contains(&name)
+}
+
+/// Decimal or `0x` hexadecimal integers, as accepted by TOML
+fn parse_integer(s: &str) -> Option<u32> {
+	match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+		Some(hex) => u32::from_str_radix(hex, 16).ok(),
+		None => s.parse().ok(),
+	}
+}
+
+/// Lines joined across `\` continuations, with the number of their first line
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+	let mut lines = vec![];
+	let mut current: Option<(usize, String)> = None;
+	for (i, line) in text.lines().enumerate() {
+		let (continued, line) = match line.strip_suffix('\\') {
+			Some(line) => (true, line),
+			None => (false, line),
+		};
+		match current.as_mut() {
+			Some((_, joined)) => {
+				joined.push(' ');
+				joined.push_str(line.trim_start());
+			}
+			None => current = Some((i + 1, line.to_owned())),
+		}
+		if !continued {
+			lines.extend(current.take());
+		}
+	}
+	lines.extend(current);
+	lines
+}
+
+fn strip_comment(line: &str) -> &str {
+	let mut escaped = false;
+	for (i, c) in line.char_indices() {
+		match c {
+			'#' if !escaped => return &line[..i],
+			'\\' => escaped = !escaped,
+			_ => escaped = false,
+		}
+	}
+	line
+}
+
+/// `NAME op value`, where op is one of `=`, `:=`, `::=`, `?=` or `+=`
+fn split_assignment(line: &str) -> Option<(&str, &str, &str)> {
+	let eq = line.find('=')?;
+	// A ':' before the '=' that isn't part of the operator is a rule
+	let (name, op) = ["::=", ":=", "?=", "+=", "="].iter()
+		.find(|op| line[..=eq].ends_with(*op))
+		.map(|op| (&line[..eq + 1 - op.len()], *op))?;
+	if name.contains(':') || name.trim().is_empty() {
+		return None;
+	}
+	Some((name.trim(), op, line[eq + 1..].trim()))
+}
+
+/// Translate the Makefile at `path` into `output`, refusing to replace an
+/// existing file
+pub fn import(path: &Path, output: &Path) -> Result<(), String> {
+	let text = std::fs::read_to_string(path)
+		.map_err(|e| format!("unable to read '{}': {e}", path.display()))?;
+	if output.exists() {
+		return Err(format!("'{}' already exists", output.display()));
+	}
+
+	let makefile = Makefile::parse(&text);
+	if !makefile.variables.contains_key("SH_PROGRAM") {
+		warn!("SH_PROGRAM isn't set, 'sh.program' will be missing");
+	}
+	for u in makefile.untranslated.iter() {
+		warn!("{}:{}: not translated: {} ({})", path.display(), u.line, u.text, u.reason);
+	}
+	for name in makefile.unknown() {
+		warn!("{}: {name} has no config.toml equivalent", path.display());
+	}
+
+	std::fs::write(output, makefile.to_config())
+		.map_err(|e| format!("unable to write '{}': {e}", output.display()))?;
+	info!("wrote '{}' from '{}'", output.display(), path.display());
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A Makefile like those of Yaul's examples, with a rule of its own
+	const MAKEFILE: &str = "\
+ifeq ($(strip $(YAUL_INSTALL_ROOT)),)
+  $(error Undefined YAUL_INSTALL_ROOT (install root directory))
+endif
+
+include $(YAUL_INSTALL_ROOT)/share/build.pre.mk
+
+# Each asset follows the format: <path>;<symbol>. Duplicates are removed
+BUILTIN_ASSETS+= \\
+\tassets/BALL.TGA;asset_ball_tga
+
+SRC_DIR:= src
+SH_PROGRAM:= vdp1-balls
+SH_SRCS:= \\
+\t$(SRC_DIR)/vdp1-balls.c \\
+\t$(SRC_DIR)/balls.c # the sprites
+
+SH_LIBRARIES:=
+SH_CFLAGS+= -O2 -I. -DVERSION=\\\"1.0\\\"
+
+IP_VERSION:= V1.000
+IP_RELEASE_DATE:= 20160101
+IP_AREAS:= JTUBKAEL
+IP_PERIPHERALS:= JAMKST
+IP_TITLE:= VDP1 balls
+IP_MASTER_STACK_ADDR:= 0x06004000
+IP_SLAVE_STACK_ADDR:= 0x06001E00
+IP_1ST_READ_ADDR:= 0x06004000
+IP_1ST_READ_SIZE:= 0
+
+include $(YAUL_INSTALL_ROOT)/share/build.post.mk
+
+clean-assets:
+\trm -f assets/*.o
+";
+
+	#[test]
+	fn parses_assignments() {
+		let makefile = Makefile::parse(MAKEFILE);
+		assert_eq!(makefile.variables["SH_SRCS"], ["src/vdp1-balls.c", "src/balls.c"]);
+		assert_eq!(makefile.variables["BUILTIN_ASSETS"], ["assets/BALL.TGA;asset_ball_tga"]);
+		assert_eq!(makefile.variables["SH_LIBRARIES"], Vec::<String>::new());
+		assert!(makefile.referenced.contains("SRC_DIR"));
+
+		let untranslated: Vec<(usize, &str)> = makefile.untranslated.iter()
+			.map(|u| (u.line, u.reason.as_str()))
+			.collect();
+		assert_eq!(untranslated, [
+			(1, "conditionals aren't supported"),
+			(2, "directives aren't supported"),
+			(3, "conditionals aren't supported"),
+			(32, "rules aren't supported"),
+			(33, "recipe outside of a rule"),
+		]);
+	}
+
+	#[test]
+	fn translates_to_config() {
+		let config = Makefile::parse(MAKEFILE).to_config();
+		let table: toml::Table = config.parse().unwrap();
+		assert_eq!(table["sh"]["program"].as_str(), Some("vdp1-balls"));
+		assert!(!table["sh"].as_table().unwrap().contains_key("flags"));
+		assert_eq!(table["ip"]["main-stack-addr"].as_integer(), Some(0x0600_4000));
+		assert_eq!(table["ip"]["1st-read-size"].as_integer(), Some(0));
+		assert_eq!(table["dirs"]["build"].as_str(), Some("build"));
+		assert_eq!(table["assets"][0]["name"].as_str(), Some("asset_ball_tga"));
+		assert!(config.contains("#   SH_CFLAGS has no config.toml equivalent\n"));
+		assert!(config.contains("#   SH_LIBRARIES has no config.toml equivalent\n"));
+	}
+
+	#[test]
+	fn splits_assignments() {
+		assert_eq!(split_assignment("A ::= b"), Some(("A", "::=", "b")));
+		assert_eq!(split_assignment("A?=b=c"), Some(("A", "?=", "b=c")));
+		assert_eq!(split_assignment("all: A=b"), None);
+		assert_eq!(strip_comment("A := b\\#c # d"), "A := b\\#c ");
+	}
+}
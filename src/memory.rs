@@ -0,0 +1,176 @@
+
+use std::fmt::Write;
+
+use toml::{Table, Value};
+use tracing::{trace, warn, error};
+
+use crate::elf::Section;
+
+/// Saturn addresses are mirrored in the upper bits (cache-through, etc.)
+const ADDRESS_MASK: u32 = 0x07FF_FFFF;
+
+/// A range of Saturn memory the program is linked into
+#[derive(Debug, Clone)]
+pub struct Region {
+	pub name: String,
+	pub origin: u32,
+	pub length: u32,
+}
+
+impl Region {
+	fn contains(&self, addr: u32) -> bool {
+		let addr = addr & ADDRESS_MASK;
+		self.origin <= addr && addr - self.origin < self.length
+	}
+}
+
+/// Memory budget configuration (`[memory]`)
+#[derive(Debug, Clone)]
+pub struct Budget {
+	pub regions: Vec<Region>,
+	/// Usage (percent) at which a warning is printed
+	pub warn: f64,
+	/// Usage (percent) at which the build fails
+	pub error: f64,
+}
+
+impl Budget {
+	pub fn from_config(config: &Table) -> Self {
+		let memory = config.get("memory").and_then(Value::as_table);
+
+		let regions = memory
+			.and_then(|m| m.get("regions"))
+			.and_then(Value::as_array)
+			.map(|regions| regions.iter()
+				.flat_map(|r| {
+					let name = r.get("name")?.as_str()?.to_owned();
+					let origin = r.get("origin")?.as_integer()? as u32;
+					let length = r.get("length")?.as_integer()? as u32;
+					Some(Region { name, origin, length })
+				})
+				.collect())
+			.unwrap_or_else(|| vec![
+				Region { name: "HWRAM".into(), origin: 0x0600_0000, length: 0x0010_0000 },
+				Region { name: "LWRAM".into(), origin: 0x0020_0000, length: 0x0010_0000 },
+			]);
+
+		let threshold = |key: &str, default: f64| memory
+			.and_then(|m| m.get(key))
+			.and_then(|v| v.as_float().or(v.as_integer().map(|i| i as f64)))
+			.unwrap_or(default);
+
+		let budget = Self {
+			regions,
+			warn: threshold("warn", 90.0),
+			error: threshold("error", 100.0),
+		};
+
+		trace!("memory config");
+		for region in budget.regions.iter() {
+			trace!("  {} = 0x{:08x}..0x{:08x}", region.name, region.origin, region.origin as u64 + region.length as u64);
+		}
+		trace!("  warn  = {}%", budget.warn);
+		trace!("  error = {}%", budget.error);
+
+		budget
+	}
+
+	/// Check the allocated sections of the linked program against the
+	/// configured regions and the stack addresses, appending a usage table to
+	/// `out`.
+	pub fn check(&self, sections: &[Section], stacks: &[(&str, u32)], out: &mut String) -> Result<(), String> {
+		let mut failed = false;
+		let mut used = vec![Vec::<&Section>::new(); self.regions.len()];
+
+		for section in sections.iter().filter(|s| s.is_alloc() && s.size > 0) {
+			match self.regions.iter().position(|r| r.contains(section.addr)) {
+				Some(i) => used[i].push(section),
+				None => warn!("section {} (0x{:08x}) is outside of every memory region", section.name, section.addr),
+			}
+
+			// In u64, as a malformed section could run past the end of the
+			// address space
+			let start = (section.addr & ADDRESS_MASK) as u64;
+			let end = start + section.size as u64;
+			for &(property, stack_addr) in stacks {
+				// The stacks grow downwards from their address
+				let stack_addr = (stack_addr & ADDRESS_MASK) as u64;
+				if start < stack_addr && stack_addr <= end {
+					error!("section {} (0x{start:08x}..0x{end:08x}) overlaps {property} (0x{stack_addr:08x})", section.name);
+					failed = true;
+				}
+			}
+		}
+
+		let _ = writeln!(out, "{:<8} {:>10} {:>10} {:>7}", "region", "used", "size", "usage");
+		for (region, sections) in self.regions.iter().zip(used) {
+			let total: u64 = sections.iter().map(|s| s.size as u64).sum();
+			let percent = 100.0 * total as f64 / region.length as f64;
+			let _ = writeln!(out, "{:<8} 0x{total:08x} 0x{:08x} {percent:>6.1}%", region.name, region.length);
+			for section in sections {
+				let _ = writeln!(out, "  {:<14} 0x{:08x}{}", section.name, section.size,
+					if section.is_nobits() { " (nobits)" } else { "" });
+			}
+
+			if percent >= self.error {
+				error!("{} usage ({percent:.1}%) exceeds the {}% limit", region.name, self.error);
+				failed = true;
+			} else if percent >= self.warn {
+				warn!("{} usage ({percent:.1}%) exceeds {}%", region.name, self.warn);
+			}
+		}
+
+		if failed {
+			Err("program does not fit in the configured memory".into())
+		} else {
+			Ok(())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn section(name: &str, addr: u32, size: u32) -> Section {
+		Section { name: name.into(), addr, size, flags: 0x2, kind: 1 }
+	}
+
+	fn budget(warn: f64, error: f64) -> Budget {
+		Budget {
+			regions: vec![Region { name: "HWRAM".into(), origin: 0x0600_0000, length: 0x0010_0000 }],
+			warn,
+			error,
+		}
+	}
+
+	#[test]
+	fn reports_usage() {
+		let mut out = String::new();
+		budget(90.0, 100.0).check(&[section(".text", 0x2600_4000, 0x8000)], &[], &mut out).unwrap();
+		assert!(out.contains("HWRAM    0x00008000 0x00100000    3.1%"), "{out}");
+		assert!(out.contains("  .text          0x00008000"), "{out}");
+	}
+
+	#[test]
+	fn fails_over_budget() {
+		let mut out = String::new();
+		let sections = [section(".text", 0x0600_4000, 0x0010_0000)];
+		assert!(budget(90.0, 100.0).check(&sections, &[], &mut out).is_err());
+	}
+
+	#[test]
+	fn fails_when_a_section_overlaps_a_stack() {
+		let mut out = String::new();
+		let sections = [section(".bss", 0x0600_3000, 0x2000)];
+		assert!(budget(90.0, 100.0).check(&sections, &[("ip.main-stack-addr", 0x0600_4000)], &mut out).is_err());
+		assert!(budget(90.0, 100.0).check(&sections, &[("ip.main-stack-addr", 0x0600_6000)], &mut out).is_ok());
+	}
+
+	#[test]
+	fn sections_at_the_end_of_memory_do_not_overflow() {
+		let mut out = String::new();
+		let sections = [section(".huge", 0x07FF_FF00, 0xFFFF_FFF0)];
+		assert!(budget(90.0, 100.0).check(&sections, &[("ip.main-stack-addr", 0x0600_4000)], &mut out).is_ok());
+	}
+}
@@ -0,0 +1,287 @@
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::elf::Section;
+
+/// Breakdown of the linked program's size, saved next to the ELF after each
+/// link so it can be compared against the previous build.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Report {
+	/// Bytes loaded from the binary (allocated sections excluding .bss)
+	pub total: u64,
+	/// Allocated output sections
+	pub sections: BTreeMap<String, u64>,
+	/// Contribution of each input object/archive member
+	pub objects: BTreeMap<String, u64>,
+	/// Every symbol with a known size
+	pub symbols: BTreeMap<String, u64>,
+}
+
+impl Report {
+	/// Build a report from the ELF section headers, the linker `.map` file and
+	/// the `nm -S` output in the `.sym` file.
+	pub fn generate<P: AsRef<Path>>(sections: &[Section], map: P, sym: P) -> Result<Self, String> {
+		let mut report = Self::default();
+
+		for section in sections.iter().filter(|s| s.is_alloc() && s.size > 0) {
+			report.sections.insert(section.name.clone(), section.size as u64);
+			if !section.is_nobits() {
+				report.total += section.size as u64;
+			}
+		}
+
+		let map = map.as_ref();
+		let map = std::fs::read_to_string(map)
+			.map_err(|e| format!("unable to read '{}': {e}", map.display()))?;
+		report.objects = parse_map(&map, &report.sections);
+
+		let sym = sym.as_ref();
+		let sym = std::fs::read_to_string(sym)
+			.map_err(|e| format!("unable to read '{}': {e}", sym.display()))?;
+		report.symbols = parse_sym(&sym);
+
+		Ok(report)
+	}
+
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+		let path = path.as_ref();
+		let data = std::fs::read_to_string(path)
+			.map_err(|e| format!("unable to read '{}': {e}", path.display()))?;
+		serde_json::from_str(&data)
+			.map_err(|e| format!("unable to parse '{}': {e}", path.display()))
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+		let path = path.as_ref();
+		let data = serde_json::to_string_pretty(self)
+			.map_err(|e| format!("unable to serialize size report: {e}"))?;
+		std::fs::write(path, data)
+			.map_err(|e| format!("unable to write '{}': {e}", path.display()))
+	}
+
+	pub fn print(&self, top: usize) {
+		println!("total: {} bytes", self.total);
+
+		println!("sections:");
+		for (name, size) in largest(&self.sections, usize::MAX) {
+			println!("  {size:>10}  {name}");
+		}
+
+		println!("objects:");
+		for (name, size) in largest(&self.objects, top) {
+			println!("  {size:>10}  {name}");
+		}
+
+		println!("symbols:");
+		for (name, size) in largest(&self.symbols, top) {
+			println!("  {size:>10}  {name}");
+		}
+	}
+
+	/// Print what changed since the `previous` report, largest changes first
+	pub fn print_diff(&self, previous: &Self, top: usize) {
+		println!("total: {} -> {} bytes ({:+})",
+			previous.total, self.total, self.total as i64 - previous.total as i64);
+
+		println!("sections:");
+		for (name, before, after) in changes(&previous.sections, &self.sections, usize::MAX) {
+			println!("  {:>+10}  {name} ({before} -> {after})", after as i64 - before as i64);
+		}
+
+		println!("objects:");
+		for (name, before, after) in changes(&previous.objects, &self.objects, top) {
+			println!("  {:>+10}  {name} ({before} -> {after})", after as i64 - before as i64);
+		}
+
+		println!("symbols:");
+		for (name, before, after) in changes(&previous.symbols, &self.symbols, top) {
+			println!("  {:>+10}  {name} ({before} -> {after})", after as i64 - before as i64);
+		}
+	}
+}
+
+/// Paths of the latest and previous size reports in the build directory
+pub fn report_paths(build_path: &Path, program: &str) -> (PathBuf, PathBuf) {
+	(
+		build_path.join(format!("{program}.size.json")),
+		build_path.join(format!("{program}.size.prev.json")),
+	)
+}
+
+fn largest(sizes: &BTreeMap<String, u64>, top: usize) -> Vec<(&String, u64)> {
+	let mut sizes: Vec<(&String, u64)> = sizes.iter()
+		.map(|(name, &size)| (name, size))
+		.collect();
+	sizes.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+	sizes.truncate(top);
+	sizes
+}
+
+fn changes<'a>(
+	before: &'a BTreeMap<String, u64>,
+	after: &'a BTreeMap<String, u64>,
+	top: usize,
+) -> Vec<(&'a String, u64, u64)> {
+	let mut changes: Vec<(&String, u64, u64)> = before.keys()
+		.chain(after.keys())
+		.collect::<BTreeSet<_>>()
+		.into_iter()
+		.map(|name| (name,
+			before.get(name).copied().unwrap_or(0),
+			after.get(name).copied().unwrap_or(0)))
+		.filter(|(_, a, b)| a != b)
+		.collect();
+	changes.sort_by_key(|(name, a, b)| (std::cmp::Reverse(a.abs_diff(*b)), *name));
+	changes.truncate(top);
+	changes
+}
+
+/// Sum the input sections of the GNU ld memory map per object file. Only
+/// input sections placed in one of the allocated output `sections` count.
+fn parse_map(map: &str, sections: &BTreeMap<String, u64>) -> BTreeMap<String, u64> {
+	let mut objects = BTreeMap::new();
+
+	let Some((_, memory_map)) = map.split_once("Linker script and memory map") else {
+		return objects;
+	};
+
+	let mut in_alloc_section = false;
+	let mut pending_name: Option<&str> = None;
+	for line in memory_map.lines() {
+		if !line.starts_with(' ') {
+			// Output section, e.g. `.text  0x06004000  0x1234`
+			let name = line.split_whitespace().next().unwrap_or_default();
+			in_alloc_section = sections.contains_key(name);
+			pending_name = None;
+			continue;
+		}
+		if !in_alloc_section {
+			continue;
+		}
+
+		let fields: Vec<&str> = line.split_whitespace().collect();
+		// Input section names longer than the name column wrap onto their
+		// own line, with the address, size and file on the next.
+		let (name, fields) = match (pending_name.take(), fields.as_slice()) {
+			(None, [name]) if line.starts_with(" .") || line.starts_with(" COMMON") => {
+				pending_name = Some(name);
+				continue;
+			}
+			(None, [name, rest @ ..]) => (*name, rest),
+			(Some(name), rest) => (name, rest),
+			(None, []) => continue,
+		};
+		if !(name.starts_with('.') || name == "COMMON" || name == "*fill*") {
+			continue;
+		}
+
+		let (size, file) = match fields {
+			[_, size, file @ ..] => (*size, file.join(" ")),
+			_ => continue,
+		};
+		let Some(size) = size.strip_prefix("0x").and_then(|s| u64::from_str_radix(s, 16).ok()) else {
+			continue;
+		};
+		if size == 0 {
+			continue;
+		}
+
+		let file = if name == "*fill*" { "*fill*".to_owned() } else { crate::build::revert_build_path(&file) };
+		*objects.entry(file).or_insert(0) += size;
+	}
+
+	objects
+}
+
+/// Parse `nm -S` output (`address size type name`). Symbols listed more
+/// than once at the same address are counted once, while static symbols
+/// sharing a name are summed.
+fn parse_sym(sym: &str) -> BTreeMap<String, u64> {
+	let mut symbols = BTreeMap::new();
+	let mut seen = BTreeSet::new();
+	for line in sym.lines() {
+		if let [address, size, _, name] = line.split_whitespace().collect::<Vec<_>>().as_slice() {
+			if let Ok(size) = u64::from_str_radix(size, 16) {
+				if seen.insert((*address, *name)) {
+					*symbols.entry(name.to_string()).or_insert(0) += size;
+				}
+			}
+		}
+	}
+	symbols
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MAP: &str = "\
+Archive member included to satisfy reference by file (symbol)
+
+/opt/sh2eb-elf/sh2eb-elf/lib/libyaul.a(dbgio.o)
+                              /tmp/proj/build/@tmp@proj@main.o (_dbgio_init)
+
+Memory Configuration
+
+Name             Origin             Length             Attributes
+*default*        0x00000000         0xffffffff
+
+Linker script and memory map
+
+                0x06004000                . = 0x6004000
+
+.text           0x06004000      0x1a0
+ *(.text .text.*)
+ .text          0x06004000       0x40 /tmp/proj/build/@tmp@proj@main.o
+                0x06004000                _main
+ .text.a_very_long_function_name
+                0x06004040      0x100 /tmp/proj/build/@tmp@proj@main.o
+ .text          0x06004140       0x5c /opt/sh2eb-elf/sh2eb-elf/lib/libyaul.a(dbgio.o)
+ *fill*         0x0600419c        0x4 
+ .text          0x060041a0        0x0 /tmp/proj/build/@tmp@proj@empty.o
+
+.bss            0x060041a0       0x20
+ COMMON         0x060041a0       0x20 /tmp/proj/build/@tmp@proj@main.o
+
+.comment        0x00000000       0x12
+ .comment       0x00000000       0x12 /tmp/proj/build/@tmp@proj@main.o
+";
+
+	#[test]
+	fn sums_input_sections_per_object() {
+		let sections = BTreeMap::from([(".text".to_owned(), 0x1a0), (".bss".to_owned(), 0x20)]);
+		let objects = parse_map(MAP, &sections);
+		assert_eq!(objects, BTreeMap::from([
+			("/tmp/proj/main.o".to_owned(), 0x40 + 0x100 + 0x20),
+			("/opt/sh2eb-elf/sh2eb-elf/lib/libyaul.a(dbgio.o)".to_owned(), 0x5c),
+			("*fill*".to_owned(), 0x4),
+		]));
+	}
+
+	#[test]
+	fn ignores_maps_without_a_memory_map() {
+		assert!(parse_map("Memory Configuration\n", &BTreeMap::new()).is_empty());
+	}
+
+	#[test]
+	fn parses_nm_sizes() {
+		let sym = "\
+06004000 00000040 T _main
+06004040 00000100 t _a_very_long_function_name
+060041a0 00000020 B _buffer
+06004000 T _start
+060041a0 00000020 B _buffer
+060041c0 00000008 b _count
+060041c8 00000008 b _count
+";
+		assert_eq!(parse_sym(sym), BTreeMap::from([
+			("_main".to_owned(), 0x40),
+			("_a_very_long_function_name".to_owned(), 0x100),
+			("_buffer".to_owned(), 0x20),
+			("_count".to_owned(), 0x10),
+		]));
+	}
+}
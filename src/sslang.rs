@@ -0,0 +1,100 @@
+
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+use tracing::trace;
+
+use crate::build::Context;
+use crate::tool::{self, Invocation, Tool};
+
+/// Extension of sslang sources in `sh.srcs`
+pub const EXTENSION: &str = "ss";
+
+/// Value of `sslang.emit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+	/// SH-2 assembly, assembled by the tool-chain's GCC
+	Asm,
+	/// Objects, linked as they are
+	Object,
+}
+
+/// The sslang compiler (`[sslang]`)
+#[derive(Debug, Clone)]
+pub struct Sslang {
+	/// Path to the compiler
+	pub compiler: String,
+	/// Arguments, with `{src}`, `{out}` and `{depfile}` replaced for each source
+	pub args: Vec<String>,
+	pub emit: Emit,
+}
+
+impl Sslang {
+	pub fn from_config(config: &Table) -> Result<Self, String> {
+		let sslang = config.get("sslang");
+		let compiler = sslang
+			.and_then(|s| s.get("compiler"))
+			.and_then(Value::as_str)
+			.unwrap_or("sslang")
+			.to_owned();
+		let emit = match sslang.and_then(|s| s.get("emit")) {
+			Some(Value::String(v)) if v == "asm" => Emit::Asm,
+			Some(Value::String(v)) if v == "object" => Emit::Object,
+			Some(v) => return Err(format!("invalid sslang.emit = {v} (expected \"asm\" or \"object\")")),
+			None => Emit::Asm,
+		};
+		let args: Vec<String> = match sslang.and_then(|s| s.get("args")) {
+			Some(Value::Array(args)) => args.iter()
+				.map(|arg| arg.as_str()
+					.map(str::to_owned)
+					.ok_or_else(|| format!("invalid sslang.args entry {arg} (expected a string)")))
+				.collect::<Result<_, _>>()?,
+			Some(v) => return Err(format!("invalid sslang.args = {v} (expected a string array)")),
+			None => ["-o", "{out}", "{src}"].map(str::to_owned).to_vec(),
+		};
+		if !args.iter().any(|arg| arg.contains("{src}")) || !args.iter().any(|arg| arg.contains("{out}")) {
+			return Err("sslang.args must contain '{src}' and '{out}'".to_owned());
+		}
+
+		trace!("sslang config");
+		trace!("  compiler = '{compiler}'");
+		trace!("  args     = [{}]", args.join(","));
+		trace!("  emit     = {emit:?}");
+
+		Ok(Self { compiler, args, emit })
+	}
+
+	/// Whether the compiler writes a Make-style dependency file
+	pub fn writes_depfile(&self) -> bool {
+		self.args.iter().any(|arg| arg.contains("{depfile}"))
+	}
+}
+
+impl Tool for Sslang {
+	fn name(&self) -> &str {
+		"SSLANG"
+	}
+
+	fn extensions(&self) -> Vec<String> {
+		vec![EXTENSION.into()]
+	}
+
+	/// Assembly is written next to the object and assembled by [`tool::Asm`]
+	fn output(&self, _src: &Path, object: &Path) -> PathBuf {
+		match self.emit {
+			Emit::Asm => object.with_extension("s"),
+			Emit::Object => object.to_owned(),
+		}
+	}
+
+	fn depfile(&self, output: &Path) -> Option<PathBuf> {
+		self.writes_depfile().then(|| output.with_extension("d"))
+	}
+
+	fn invocation(&self, _ctx: &Context, src: &Path, output: &Path) -> Invocation {
+		Invocation::Command {
+			program: self.compiler.clone(),
+			args: tool::substitute(&self.args, src, output, &output.with_extension("d")),
+		}
+	}
+}
@@ -0,0 +1,155 @@
+
+use std::path::Path;
+
+use tracing::info;
+
+/// Language of the `main` source created by `new`/`init`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+	#[default]
+	C,
+	Cxx,
+	Sslang,
+}
+
+impl Lang {
+	pub fn parse(s: &str) -> Option<Self> {
+		match s {
+			"c" => Some(Self::C),
+			"c++" | "cxx" | "cpp" => Some(Self::Cxx),
+			"sslang" => Some(Self::Sslang),
+			_ => None,
+		}
+	}
+
+	fn main_file(self) -> &'static str {
+		match self {
+			Self::C => "main.c",
+			Self::Cxx => "main.cxx",
+			Self::Sslang => "main.ss",
+		}
+	}
+
+	fn main_source(self) -> &'static str {
+		match self {
+			Self::C => MAIN_C,
+			Self::Cxx => MAIN_CXX,
+			Self::Sslang => MAIN_SS,
+		}
+	}
+}
+
+const MAIN_C: &str = r#"#include <yaul.h>
+
+#include <stdio.h>
+
+void
+main(void)
+{
+        dbgio_init();
+        dbgio_dev_default_init(DBGIO_DEV_VDP2_ASYNC);
+        dbgio_dev_font_load();
+
+        dbgio_puts("Hello, World!\n");
+
+        while (true) {
+                dbgio_flush();
+                vdp2_sync();
+                vdp2_sync_wait();
+        }
+}
+"#;
+
+const MAIN_CXX: &str = r#"#include <yaul.h>
+
+static constexpr const char *greeting = "Hello, World!\n";
+
+int
+main()
+{
+        dbgio_init();
+        dbgio_dev_default_init(DBGIO_DEV_VDP2_ASYNC);
+        dbgio_dev_font_load();
+
+        dbgio_puts(greeting);
+
+        while (true) {
+                dbgio_flush();
+                vdp2_sync();
+                vdp2_sync_wait();
+        }
+}
+"#;
+
+const MAIN_SS: &str = "// Entry point, called once the IP and 1st read file are loaded
+fn main() {
+}
+";
+
+const GITIGNORE: &str = "/build/
+/cd/
+/audio-tracks/
+*.iso
+*.cue
+";
+
+/// `config.toml` matching the `ss.mk` template
+fn config(name: &str, lang: Lang) -> String {
+	let title = name.to_uppercase();
+	let main = lang.main_file();
+	format!(r#"assets = []
+
+[dirs]
+build = "build"
+image = "cd"
+audio = "audio"
+assets = "assets"
+output = "."
+
+[sh]
+program = "{name}"
+symbols = []
+srcs = ["{main}"]
+
+[ip]
+version = "V1.000"
+release-date = 19940101
+areas = "JTUBKAEL"
+peripherals = "JAMKST"
+title = "{title}"
+main-stack-addr = 0x06004000
+sub-stack-addr = 0x06001E00
+1st-read-addr = 0x06004000
+1st-read-size = 0
+{sslang}"#, sslang = match lang {
+		Lang::Sslang => "\n[sslang]\ncompiler = \"sslang\"\nargs = [\"-o\", \"{out}\", \"{src}\"]\nemit = \"asm\"\n",
+		_ => "",
+	})
+}
+
+/// Write the template project into `dir`, named `name`. Existing files are
+/// never overwritten.
+pub fn create(dir: &Path, name: &str, lang: Lang) -> Result<(), String> {
+	if name.is_empty() || name.contains(char::is_whitespace) {
+		return Err(format!("invalid project name '{name}' (expected a name without spaces)"));
+	}
+
+	let files = [
+		("config.toml", config(name, lang)),
+		(lang.main_file(), lang.main_source().to_owned()),
+		(".gitignore", GITIGNORE.to_owned()),
+	];
+	if let Some((file, _)) = files.iter().find(|(file, _)| dir.join(file).exists()) {
+		return Err(format!("'{}' already exists", dir.join(file).display()));
+	}
+
+	std::fs::create_dir_all(dir)
+		.map_err(|e| format!("unable to create '{}': {e}", dir.display()))?;
+	for (file, contents) in files.iter() {
+		let path = dir.join(file);
+		std::fs::write(&path, contents)
+			.map_err(|e| format!("unable to write '{}': {e}", path.display()))?;
+	}
+	info!("created project '{name}' in '{}'", dir.display());
+	Ok(())
+}
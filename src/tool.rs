@@ -0,0 +1,319 @@
+
+use std::path::{Path, PathBuf};
+
+use toml::{Table, Value};
+use tracing::trace;
+
+use crate::build::Context;
+use crate::graph::Action;
+
+/// How a tool builds one source
+pub enum Invocation {
+	/// Run a program
+	Command {
+		program: String,
+		args: Vec<String>,
+	},
+	/// Run Rust code inside ssmake, reading the source (first) and writing
+	/// the output (second). These can't be exported. None of the built-in
+	/// tools need it yet.
+	#[allow(dead_code)]
+	InProcess {
+		description: String,
+		run: fn(&Path, &Path) -> std::io::Result<()>,
+	},
+}
+
+impl Invocation {
+	/// The action building `src` into `output`
+	pub fn into_action<'a>(self, src: &Path, output: &Path) -> Action<'a> {
+		match self {
+			Invocation::Command { program, args } => Action::Command { program, args, stdout: None, quiet: false },
+			Invocation::InProcess { description, run } => {
+				let (src, output) = (src.to_owned(), output.to_owned());
+				let failed = format!("{description} failed");
+				Action::Call {
+					description,
+					shell: None,
+					f: Box::new(move |_| run(&src, &output).map_err(|e| format!("{failed}: {e}"))),
+				}
+			}
+		}
+	}
+}
+
+/// A front end building sources with one of its extensions. The result is
+/// the object, or another source passed on to the tool for its extension
+/// (e.g. sslang emitting assembly).
+pub trait Tool {
+	/// Short name, shown in exported build files
+	fn name(&self) -> &str;
+
+	/// Extensions of the sources it builds, without the dot
+	fn extensions(&self) -> Vec<String>;
+
+	/// What building `src` writes, on its way to `object`
+	fn output(&self, _src: &Path, object: &Path) -> PathBuf {
+		object.to_owned()
+	}
+
+	/// Make-style dependency file written next to `output`, listing more
+	/// inputs (e.g. headers)
+	fn depfile(&self, _output: &Path) -> Option<PathBuf> {
+		None
+	}
+
+	/// Inputs besides the source
+	fn inputs(&self, _ctx: &Context) -> Vec<PathBuf> {
+		vec![]
+	}
+
+	/// Whether its objects may come from the object cache
+	fn cacheable(&self) -> bool {
+		false
+	}
+
+	fn invocation(&self, ctx: &Context, src: &Path, output: &Path) -> Invocation;
+}
+
+/// One use of a tool, building `src` into `output` on the way to `object`
+pub struct Job<'a> {
+	pub tool: &'a dyn Tool,
+	pub src: PathBuf,
+	pub output: PathBuf,
+	pub object: PathBuf,
+}
+
+/// Replace `{src}`, `{out}` and `{depfile}` in `args`
+pub fn substitute(args: &[String], src: &Path, output: &Path, depfile: &Path) -> Vec<String> {
+	args.iter()
+		.map(|arg| arg
+			.replace("{src}", &src.display().to_string())
+			.replace("{out}", &output.display().to_string())
+			.replace("{depfile}", &depfile.display().to_string()))
+		.collect()
+}
+
+/// The tool-chain's GCC inputs: the recorded versions, the configuration the
+/// flags come from and libyaul when it's built from source
+fn gcc_inputs(ctx: &Context) -> Vec<PathBuf> {
+	[ctx.build_toolchain.clone(), ctx.config.path.clone()].into_iter()
+		.chain(ctx.libyaul_library())
+		.collect()
+}
+
+fn gcc(ctx: &Context, compiler: &str, options: Vec<String>) -> Invocation {
+	let (program, args) = ctx.compile_command(compiler, options);
+	Invocation::Command { program, args }
+}
+
+/// C, compiled by the tool-chain's GCC
+pub struct Cc;
+
+impl Tool for Cc {
+	fn name(&self) -> &str {
+		"CC"
+	}
+
+	fn extensions(&self) -> Vec<String> {
+		vec!["c".into()]
+	}
+
+	fn depfile(&self, output: &Path) -> Option<PathBuf> {
+		Some(output.with_extension("d"))
+	}
+
+	fn inputs(&self, ctx: &Context) -> Vec<PathBuf> {
+		gcc_inputs(ctx)
+	}
+
+	fn cacheable(&self) -> bool {
+		true
+	}
+
+	fn invocation(&self, ctx: &Context, src: &Path, output: &Path) -> Invocation {
+		gcc(ctx, &ctx.sh_cc, ctx.build_c_options(src, output))
+	}
+}
+
+/// C++, compiled by the tool-chain's G++
+pub struct Cxx;
+
+impl Tool for Cxx {
+	fn name(&self) -> &str {
+		"CXX"
+	}
+
+	fn extensions(&self) -> Vec<String> {
+		["cxx", "cpp", "cc", "C"].map(str::to_owned).to_vec()
+	}
+
+	fn depfile(&self, output: &Path) -> Option<PathBuf> {
+		Some(output.with_extension("d"))
+	}
+
+	fn inputs(&self, ctx: &Context) -> Vec<PathBuf> {
+		gcc_inputs(ctx)
+	}
+
+	fn cacheable(&self) -> bool {
+		true
+	}
+
+	fn invocation(&self, ctx: &Context, src: &Path, output: &Path) -> Invocation {
+		gcc(ctx, &ctx.sh_cxx, ctx.build_cxx_options(src, output))
+	}
+}
+
+/// SH-2 assembly, preprocessed (`.sx`) or not (`.s`), assembled by the
+/// tool-chain's GCC
+pub struct Asm;
+
+impl Tool for Asm {
+	fn name(&self) -> &str {
+		"AS"
+	}
+
+	fn extensions(&self) -> Vec<String> {
+		vec!["sx".into(), "s".into()]
+	}
+
+	fn inputs(&self, ctx: &Context) -> Vec<PathBuf> {
+		gcc_inputs(ctx)
+	}
+
+	fn invocation(&self, ctx: &Context, src: &Path, output: &Path) -> Invocation {
+		gcc(ctx, &ctx.sh_cc, ctx.build_asm_options(src, output))
+	}
+}
+
+/// A program from `[tools.<name>]`
+#[derive(Debug, Clone)]
+pub struct External {
+	pub name: String,
+	pub extensions: Vec<String>,
+	/// Program followed by its arguments, with `{src}`, `{out}` and
+	/// `{depfile}` replaced for each source
+	pub command: Vec<String>,
+	/// Extension of what it writes, `o` for objects
+	pub output: String,
+}
+
+impl External {
+	fn from_config(name: &str, tool: &Value) -> Result<Self, String> {
+		let strings = |key: &str| -> Result<Vec<String>, String> {
+			match tool.get(key) {
+				Some(Value::Array(values)) if values.iter().all(Value::is_str) => Ok(values.iter()
+					.flat_map(Value::as_str)
+					.map(str::to_owned)
+					.collect()),
+				Some(Value::String(value)) if key == "extensions" => Ok(vec![value.clone()]),
+				Some(v) => Err(format!("invalid tools.{name}.{key} = {v} (expected a string array)")),
+				None => Err(format!("missing tools.{name}.{key} = [] (string array)")),
+			}
+		};
+		let extensions: Vec<String> = strings("extensions")?.into_iter()
+			.map(|ext| ext.trim_start_matches('.').to_owned())
+			.collect();
+		let command = strings("command")?;
+		if command.is_empty() || !command.iter().any(|arg| arg.contains("{out}")) {
+			return Err(format!("tools.{name}.command must name a program and contain '{{out}}'"));
+		}
+		let output = match tool.get("output") {
+			Some(Value::String(v)) => v.trim_start_matches('.').to_owned(),
+			Some(v) => return Err(format!("invalid tools.{name}.output = {v} (expected an extension, e.g. \"o\" or \"s\")")),
+			None => "o".to_owned(),
+		};
+
+		trace!("tools.{name} config");
+		trace!("  extensions = [{}]", extensions.join(","));
+		trace!("  command    = [{}]", command.join(","));
+		trace!("  output     = '{output}'");
+
+		Ok(Self { name: name.to_owned(), extensions, command, output })
+	}
+}
+
+impl Tool for External {
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	fn extensions(&self) -> Vec<String> {
+		self.extensions.clone()
+	}
+
+	fn output(&self, _src: &Path, object: &Path) -> PathBuf {
+		object.with_extension(&self.output)
+	}
+
+	fn depfile(&self, output: &Path) -> Option<PathBuf> {
+		self.command.iter()
+			.any(|arg| arg.contains("{depfile}"))
+			.then(|| output.with_extension("d"))
+	}
+
+	fn invocation(&self, _ctx: &Context, src: &Path, output: &Path) -> Invocation {
+		let depfile = output.with_extension("d");
+		let mut command = substitute(&self.command, src, output, &depfile).into_iter();
+		Invocation::Command {
+			program: command.next().unwrap_or_default(),
+			args: command.collect(),
+		}
+	}
+}
+
+/// Tools from `[tools]`, which take precedence over the built-in ones for
+/// the same extension
+pub fn from_config(config: &Table) -> Result<Vec<Box<dyn Tool>>, String> {
+	match config.get("tools") {
+		Some(Value::Table(tools)) => tools.iter()
+			.map(|(name, tool)| External::from_config(name, tool).map(|tool| Box::new(tool) as Box<dyn Tool>))
+			.collect(),
+		Some(v) => Err(format!("invalid tools = {v} (expected a table of tools)")),
+		None => Ok(vec![]),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::graph::{Graph, Mtime, Node, Options, Reporter};
+
+	struct Quiet;
+
+	impl Reporter for Quiet {}
+
+	/// A tool upper-casing text, run in-process
+	fn upper(src: &Path, output: &Path) -> std::io::Result<()> {
+		let text = std::fs::read_to_string(src)?;
+		std::fs::write(output, text.to_uppercase())
+	}
+
+	#[test]
+	fn runs_in_process_tools() {
+		let dir = std::env::temp_dir().join(format!("ssmake-tool-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let (src, output) = (dir.join("hello.txt"), dir.join("hello.up"));
+		std::fs::write(&src, "hello").unwrap();
+
+		let run = || {
+			let invocation = Invocation::InProcess { description: "upper hello.txt".into(), run: upper };
+			let mut graph = Graph::new();
+			graph.add(Node::new("hello.txt")
+				.input(&src)
+				.output(&output)
+				.action(invocation.into_action(&src, &output)));
+			graph.run(&Mtime, Options::default(), &Quiet)
+		};
+		assert_eq!(run(), Ok(1));
+		assert_eq!(std::fs::read_to_string(&output).unwrap(), "HELLO");
+		assert_eq!(run(), Ok(0));
+
+		std::fs::remove_file(&src).unwrap();
+		std::fs::remove_file(&output).unwrap();
+		let failed = run();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert!(failed.is_err_and(|e| e.starts_with("hello.txt: upper hello.txt failed: ")));
+	}
+}
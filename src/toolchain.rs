@@ -0,0 +1,380 @@
+
+use std::path::{Path, PathBuf};
+
+use duct::cmd;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+use tracing::{trace, debug, info, warn};
+
+use crate::cache;
+
+/// SH-2 tool-chain prefixes, in order of preference
+const KNOWN_PREFIXES: [&str; 2] = ["sh2eb-elf", "sh-elf"];
+
+/// Where tool-chains are usually installed, besides `PATH`. `~` is `$HOME`.
+const KNOWN_LOCATIONS: [&str; 4] = [
+	"/opt/tool-chains/sh2eb-elf",
+	"/opt/tool-chains/sh-elf",
+	"~/.local/x-tools/sh2eb-elf",
+	"~/.local/x-tools/sh-elf",
+];
+
+/// An installed SH-2 tool-chain with Yaul
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Toolchain {
+	/// `YAUL_INSTALL_ROOT`
+	pub install_root: String,
+	/// `YAUL_ARCH_SH_PREFIX`, the directory holding libyaul's headers
+	pub arch_prefix: String,
+	/// `YAUL_PROG_SH_PREFIX`, the prefix of the tool-chain's programs
+	pub prog_prefix: String,
+}
+
+impl Toolchain {
+	/// Tool-chain program, e.g. `program("gcc")` is `<root>/bin/sh2eb-elf-gcc`
+	pub fn program(&self, tool: &str) -> String {
+		format!("{}/bin/{}-{tool}", self.install_root, self.prog_prefix)
+	}
+
+	/// Yaul tool installed next to the tool-chain, e.g. `make-ip`
+	pub fn tool(&self, name: &str) -> String {
+		format!("{}/bin/{name}", self.install_root)
+	}
+
+	/// File under `<root>/share`
+	pub fn share(&self, path: &str) -> String {
+		format!("{}/share/{path}", self.install_root)
+	}
+
+	/// libyaul's headers
+	pub fn include_dir(&self) -> String {
+		format!("{}/{}/include/yaul", self.install_root, self.arch_prefix)
+	}
+
+	/// The tool-chain installed in `root`, if there is one. Prefixes not
+	/// given are worked out from the files in `root`.
+	fn in_root(root: &Path, arch_prefix: Option<&str>, prog_prefix: Option<&str>) -> Option<Self> {
+		let prog_prefix = match prog_prefix {
+			Some(prefix) => prefix.to_owned(),
+			None => KNOWN_PREFIXES.iter()
+				.find(|prefix| root.join("bin").join(format!("{prefix}-gcc")).is_file())?
+				.to_string(),
+		};
+		if !root.join("bin").join(format!("{prog_prefix}-gcc")).is_file() {
+			return None;
+		}
+		// Yaul may be installed under another prefix than the programs, e.g.
+		// sh2eb-elf-gcc with the headers in sh-elf/include/yaul
+		let arch_prefix = arch_prefix.map(str::to_owned).unwrap_or_else(|| {
+			std::iter::once(prog_prefix.as_str())
+				.chain(KNOWN_PREFIXES)
+				.find(|prefix| root.join(prefix).join("include/yaul").is_dir())
+				.unwrap_or(&prog_prefix)
+				.to_owned()
+		});
+		Some(Self {
+			install_root: root.display().to_string(),
+			arch_prefix,
+			prog_prefix,
+		})
+	}
+
+	/// Find the tool-chain, in order:
+	///   1. `toolchain.install-root` in `config.toml`
+	///   2. the `YAUL_INSTALL_ROOT` environment variable
+	///   3. a `<prefix>-gcc` on `PATH`
+	///   4. the result of the last search of the usual install locations
+	///   5. the usual install locations
+	///
+	/// `toolchain.arch-prefix`/`prog-prefix` and the `YAUL_ARCH_SH_PREFIX`/
+	/// `YAUL_PROG_SH_PREFIX` environment variables override the prefixes.
+	pub fn discover(config: &Table) -> Result<Self, String> {
+		let table = config.get("toolchain");
+		let setting = |key: &str, var: &str| table
+			.and_then(|t| t.get(key))
+			.and_then(Value::as_str)
+			.map(str::to_owned)
+			.or_else(|| std::env::var(var).ok())
+			.filter(|value| !value.trim().is_empty());
+		let arch_prefix = setting("arch-prefix", "YAUL_ARCH_SH_PREFIX");
+		let prog_prefix = setting("prog-prefix", "YAUL_PROG_SH_PREFIX").or(arch_prefix.clone());
+
+		let toolchain = match setting("install-root", "YAUL_INSTALL_ROOT") {
+			Some(root) => Self::in_root(Path::new(&root), arch_prefix.as_deref(), prog_prefix.as_deref())
+				.ok_or_else(|| format!("no {}-gcc in '{root}/bin' (YAUL_INSTALL_ROOT or toolchain.install-root)",
+					prog_prefix.as_deref().unwrap_or("sh2eb-elf")))?,
+			None => match Self::on_path(arch_prefix.as_deref(), prog_prefix.as_deref()) {
+				Some(found) => found,
+				None => match Self::load_cached().filter(|cached| {
+					arch_prefix.as_ref().is_none_or(|prefix| *prefix == cached.arch_prefix)
+						&& prog_prefix.as_ref().is_none_or(|prefix| *prefix == cached.prog_prefix)
+				}) {
+					Some(cached) => cached,
+					None => {
+						let found = Self::in_known_locations(arch_prefix.as_deref(), prog_prefix.as_deref())
+							.ok_or_else(|| format!("unable to find an SH-2 tool-chain ({}-gcc on PATH or in {}); set YAUL_INSTALL_ROOT or toolchain.install-root",
+								KNOWN_PREFIXES.join("-gcc/"),
+								KNOWN_LOCATIONS.join(", ")))?;
+						found.save_cached();
+						found
+					}
+				},
+			},
+		};
+
+		for (name, value) in [
+			("YAUL_INSTALL_ROOT (install root directory)", &toolchain.install_root),
+			("YAUL_ARCH_SH_PREFIX (tool-chain prefix)", &toolchain.arch_prefix),
+			("YAUL_PROG_SH_PREFIX (tool-chain program prefix)", &toolchain.prog_prefix),
+		] {
+			if value.contains(' ') {
+				return Err(format!("{name} contains spaces"));
+			}
+		}
+
+		trace!("tool-chain");
+		trace!("  install-root = '{}'", toolchain.install_root);
+		trace!("  arch-prefix  = '{}'", toolchain.arch_prefix);
+		trace!("  prog-prefix  = '{}'", toolchain.prog_prefix);
+		Ok(toolchain)
+	}
+
+	/// Look for `<prefix>-gcc` on `PATH`
+	fn on_path(arch_prefix: Option<&str>, prog_prefix: Option<&str>) -> Option<Self> {
+		let prefixes: Vec<&str> = match prog_prefix {
+			Some(prefix) => vec![prefix],
+			None => KNOWN_PREFIXES.to_vec(),
+		};
+		std::env::var_os("PATH")
+			.map(|path| std::env::split_paths(&path).collect::<Vec<PathBuf>>())
+			.unwrap_or_default()
+			.into_iter()
+			.filter(|dir| prefixes.iter().any(|prefix| dir.join(format!("{prefix}-gcc")).is_file()))
+			.flat_map(|dir| dir.parent().map(Path::to_path_buf))
+			.inspect(|root| debug!("looking for a tool-chain in '{}'", root.display()))
+			.find_map(|root| Self::in_root(&root, arch_prefix, prog_prefix))
+	}
+
+	/// Look in the usual install locations
+	fn in_known_locations(arch_prefix: Option<&str>, prog_prefix: Option<&str>) -> Option<Self> {
+		let home = std::env::var("HOME").unwrap_or_default();
+		KNOWN_LOCATIONS.iter()
+			.map(|location| PathBuf::from(location.replacen('~', &home, 1)))
+			.inspect(|root| debug!("looking for a tool-chain in '{}'", root.display()))
+			.find_map(|root| Self::in_root(&root, arch_prefix, prog_prefix))
+	}
+
+	/// Ask GCC for its version and read libyaul's
+	pub fn versions(&self) -> Result<Versions, String> {
+		let gcc = self.program("gcc");
+		// Newer GCCs only print the major version for -dumpversion
+		let version = cmd!(&gcc, "-dumpfullversion", "-dumpversion")
+			.stdin_null()
+			.stderr_null()
+			.read()
+			.map_err(|e| format!("unable to get the version of '{gcc}': {e}"))?;
+		Ok(Versions {
+			install_root: self.install_root.clone(),
+			prog_prefix: self.prog_prefix.clone(),
+			gcc: version.trim().to_owned(),
+			yaul: self.yaul_version(),
+		})
+	}
+
+	/// libyaul's version, from `yaul/version.h` or `share/yaul/VERSION`
+	fn yaul_version(&self) -> Option<String> {
+		if let Ok(header) = std::fs::read_to_string(format!("{}/version.h", self.include_dir())) {
+			let define = |name: &str| header.lines()
+				.flat_map(|line| line.trim().strip_prefix("#define "))
+				.flat_map(|line| line.split_once(char::is_whitespace))
+				.find(|(define, _)| *define == name)
+				.map(|(_, value)| value.trim().trim_matches('"').to_owned());
+			if let Some(version) = define("YAUL_VERSION") {
+				return Some(version);
+			}
+			let parts: Option<Vec<String>> = ["YAUL_VERSION_MAJOR", "YAUL_VERSION_MINOR", "YAUL_VERSION_PATCH"]
+				.iter()
+				.map(|name| define(name))
+				.collect();
+			if let Some(parts) = parts {
+				return Some(parts.join("."));
+			}
+		}
+		std::fs::read_to_string(self.share("yaul/VERSION"))
+			.ok()
+			.map(|version| version.trim().to_owned())
+			.filter(|version| !version.is_empty())
+	}
+
+	fn cache_path() -> PathBuf {
+		cache::default_dir().join("toolchain.json")
+	}
+
+	/// The last search of the usual locations, if the tool-chain is still there
+	fn load_cached() -> Option<Self> {
+		let data = std::fs::read_to_string(Self::cache_path()).ok()?;
+		let cached: Self = serde_json::from_str(&data).ok()?;
+		let current = Self::in_root(Path::new(&cached.install_root),
+			Some(&cached.arch_prefix), Some(&cached.prog_prefix))?;
+		debug!("using cached tool-chain '{}'", current.install_root);
+		Some(current)
+	}
+
+	fn save_cached(&self) {
+		let path = Self::cache_path();
+		let result = path.parent()
+			.map(std::fs::create_dir_all)
+			.transpose()
+			.and_then(|_| std::fs::write(&path, serde_json::to_string(self).unwrap_or_default()));
+		if let Err(e) = result {
+			warn!("unable to cache the tool-chain location in '{}': {e}", path.display());
+		}
+	}
+}
+
+/// `toolchain.launcher`, the command compiles are run through (e.g. ccache),
+/// as a program name or a command line array
+pub fn launcher(config: &Table) -> Result<Vec<String>, String> {
+	let launcher = match config.get("toolchain").and_then(|toolchain| toolchain.get("launcher")) {
+		None => vec![],
+		Some(Value::String(v)) => v.split_whitespace().map(str::to_owned).collect(),
+		Some(Value::Array(args)) if args.iter().all(Value::is_str) => args.iter()
+			.flat_map(Value::as_str)
+			.map(str::to_owned)
+			.collect(),
+		Some(v) => return Err(format!("invalid toolchain.launcher = {v} (expected \"program\" or [\"program\", \"args\"])")),
+	};
+	trace!("launcher = [{}]", launcher.join(","));
+	Ok(launcher)
+}
+
+/// Versions of the tool-chain a build used, kept in the build directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Versions {
+	pub install_root: String,
+	pub prog_prefix: String,
+	pub gcc: String,
+	/// `None` when the installed libyaul doesn't record its version
+	pub yaul: Option<String>,
+}
+
+/// Whether `version` satisfies `requirement`: either a version prefix
+/// ("12" or "12.2" match 12.2.0) or ">=" followed by a minimum version
+fn satisfies(version: &str, requirement: &str) -> bool {
+	let parse = |v: &str| v.trim()
+		.split('.')
+		.map(|part| part.parse::<u32>().ok())
+		.collect::<Option<Vec<u32>>>();
+	let Some(have) = parse(version) else {
+		return version.trim() == requirement.trim();
+	};
+	match requirement.trim().strip_prefix(">=") {
+		Some(minimum) => parse(minimum).is_some_and(|minimum| have >= minimum),
+		None => parse(requirement).is_some_and(|want| have.starts_with(&want)),
+	}
+}
+
+impl Versions {
+	/// Path of the recorded versions in `build_path`
+	pub fn path(build_path: &Path) -> PathBuf {
+		build_path.join("toolchain.json")
+	}
+
+	/// Check `toolchain.gcc-version` and `toolchain.yaul-version`. The latter
+	/// is skipped when libyaul is built from `libyaul.source`, since the
+	/// installed one isn't used.
+	pub fn check(&self, config: &Table) -> Result<(), String> {
+		let requirement = |key: &str| config.get("toolchain")
+			.and_then(|toolchain| toolchain.get(key))
+			.and_then(Value::as_str);
+
+		if let Some(want) = requirement("gcc-version") {
+			if !satisfies(&self.gcc, want) {
+				return Err(format!("toolchain.gcc-version = \"{want}\" but {}-gcc in '{}' is {}",
+					self.prog_prefix, self.install_root, self.gcc));
+			}
+		}
+		let from_source = config.get("libyaul")
+			.and_then(|libyaul| libyaul.get("source"))
+			.and_then(Value::as_str)
+			.is_some();
+		if let Some(want) = requirement("yaul-version") {
+			if from_source {
+				debug!("ignoring toolchain.yaul-version = \"{want}\", libyaul is built from libyaul.source");
+				return Ok(());
+			}
+			match &self.yaul {
+				Some(yaul) if satisfies(yaul, want) => {}
+				Some(yaul) => return Err(format!("toolchain.yaul-version = \"{want}\" but the libyaul in '{}' is {yaul}",
+					self.install_root)),
+				None => return Err(format!("toolchain.yaul-version = \"{want}\" but the libyaul in '{}' doesn't record its version",
+					self.install_root)),
+			}
+		}
+		Ok(())
+	}
+
+	/// Save the versions to `path`. The file is only rewritten when they
+	/// change, so nodes depending on it rebuild after a tool-chain update.
+	pub fn record(&self, path: &Path) -> Result<(), String> {
+		let previous: Option<Self> = std::fs::read_to_string(path)
+			.ok()
+			.and_then(|data| serde_json::from_str(&data).ok());
+		if previous.as_ref() == Some(self) {
+			return Ok(());
+		}
+		if let Some(previous) = previous {
+			info!("tool-chain changed (gcc {} -> {}), rebuilding", previous.gcc, self.gcc);
+		}
+		let data = serde_json::to_string_pretty(self)
+			.map_err(|e| format!("unable to serialize tool-chain versions: {e}"))?;
+		std::fs::write(path, data + "\n")
+			.map_err(|e| format!("unable to write '{}': {e}", path.display()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn matches_version_prefixes() {
+		assert!(satisfies("12.2.0\n", "12"));
+		assert!(satisfies("12.2.0", "12.2"));
+		assert!(satisfies("12.2.0", "12.2.0"));
+		assert!(!satisfies("12.2.0", "12.3"));
+		assert!(!satisfies("12.2.0", "1"));
+		assert!(!satisfies("12.2.0", "13"));
+	}
+
+	#[test]
+	fn matches_minimum_versions() {
+		assert!(satisfies("12.2.0", ">=12"));
+		assert!(satisfies("12.2.0", ">= 11.4"));
+		assert!(satisfies("0.3.1", ">=0.3.1"));
+		assert!(!satisfies("0.3.1", ">=0.10"));
+		assert!(!satisfies("12.2.0", ">=12.x"));
+	}
+
+	#[test]
+	fn compares_other_versions_exactly() {
+		assert!(satisfies("r1234-git", "r1234-git"));
+		assert!(!satisfies("r1234-git", ">=r1234-git"));
+	}
+
+	#[test]
+	fn checks_requirements() {
+		let versions = Versions {
+			install_root: "/opt/tool-chains/sh2eb-elf".into(),
+			prog_prefix: "sh2eb-elf".into(),
+			gcc: "14.2.0".into(),
+			yaul: None,
+		};
+		let config = |toml: &str| toml.parse::<Table>().unwrap();
+		assert!(versions.check(&config("[toolchain]\ngcc-version = \">=13\"")).is_ok());
+		assert!(versions.check(&config("[toolchain]\ngcc-version = \"13\"")).is_err());
+		assert!(versions.check(&config("[toolchain]\nyaul-version = \"0.3\"")).is_err());
+		// libyaul built from source isn't the installed one
+		assert!(versions.check(&config("[toolchain]\nyaul-version = \"0.3\"\n[libyaul]\nsource = \"yaul\"")).is_ok());
+	}
+}
@@ -0,0 +1,107 @@
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, error};
+
+use crate::diagnostic::{self, Severity};
+
+/// A warning as stored in the baseline. Line numbers are left out so the
+/// baseline survives unrelated edits.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Warning {
+	pub file: String,
+	pub option: Option<String>,
+	pub message: String,
+}
+
+/// File next to `object` holding the warnings from its last compile
+pub fn record_path(object: &Path) -> PathBuf {
+	object.with_extension("warnings")
+}
+
+/// Save the warnings in a compile's `output` next to `object`
+pub fn record(object: &Path, output: &str) -> Result<(), String> {
+	let warnings: Vec<Warning> = diagnostic::parse(output)
+		.into_iter()
+		.filter(|d| d.severity == Severity::Warning)
+		.map(|d| Warning { file: d.file, option: d.option, message: d.message })
+		.collect();
+
+	let path = record_path(object);
+	let data = serde_json::to_string(&warnings)
+		.map_err(|e| format!("unable to serialize warnings: {e}"))?;
+	std::fs::write(&path, data)
+		.map_err(|e| format!("unable to write '{}': {e}", path.display()))
+}
+
+/// Warnings recorded for every object. A header warning seen by several
+/// objects is counted once.
+pub fn collect(objects: &[PathBuf]) -> Vec<Warning> {
+	let mut warnings: Vec<Warning> = objects.iter()
+		.flat_map(|object| std::fs::read_to_string(record_path(object)).ok())
+		.flat_map(|data| serde_json::from_str::<Vec<Warning>>(&data).unwrap_or_default())
+		.collect();
+	warnings.sort();
+	warnings.dedup();
+	warnings
+}
+
+fn load(path: &Path) -> Result<Option<Vec<Warning>>, String> {
+	let Ok(data) = std::fs::read_to_string(path) else {
+		return Ok(None);
+	};
+	serde_json::from_str(&data)
+		.map(Some)
+		.map_err(|e| format!("unable to parse '{}': {e}", path.display()))
+}
+
+fn save(path: &Path, warnings: &[Warning]) -> Result<(), String> {
+	let data = serde_json::to_string_pretty(warnings)
+		.map_err(|e| format!("unable to serialize warnings: {e}"))?;
+	std::fs::write(path, data + "\n")
+		.map_err(|e| format!("unable to write '{}': {e}", path.display()))
+}
+
+fn counts(warnings: &[Warning]) -> BTreeMap<&Warning, usize> {
+	let mut counts = BTreeMap::new();
+	for warning in warnings {
+		*counts.entry(warning).or_default() += 1;
+	}
+	counts
+}
+
+/// Fail on warnings missing from the baseline at `path`. The baseline is
+/// written if it doesn't exist yet, and shrunk as warnings get fixed.
+pub fn check_baseline(path: &Path, current: Vec<Warning>) -> Result<(), String> {
+	let Some(baseline) = load(path)? else {
+		info!("recorded {} warnings in '{}'", current.len(), path.display());
+		return save(path, &current);
+	};
+
+	let allowed = counts(&baseline);
+	let new: Vec<(&Warning, usize)> = counts(&current).into_iter()
+		.filter_map(|(warning, count)| {
+			let extra = count.saturating_sub(allowed.get(warning).copied().unwrap_or(0));
+			(extra > 0).then_some((warning, extra))
+		})
+		.collect();
+
+	if !new.is_empty() {
+		for (warning, count) in new.iter() {
+			let option = warning.option.as_ref()
+				.map(|option| format!(" [{option}]"))
+				.unwrap_or_default();
+			error!("new warning: {}: {}{option}{}", warning.file, warning.message,
+				if *count > 1 { format!(" (x{count})") } else { String::new() });
+		}
+		return Err(format!("{} new warnings not in '{}'", new.len(), path.display()));
+	}
+
+	if current.len() < baseline.len() {
+		info!("{} warnings fixed, updating '{}'", baseline.len() - current.len(), path.display());
+		save(path, &current)?;
+	}
+	Ok(())
+}
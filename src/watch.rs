@@ -0,0 +1,173 @@
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+use toml::Value;
+use tracing::{trace, info, warn, error};
+
+use crate::{build, graph};
+use crate::config::Config;
+use crate::emulator::Emulator;
+
+/// Files and directories a build depends on
+#[derive(Debug, Default)]
+struct Inputs {
+	files: Vec<PathBuf>,
+	/// Watched recursively
+	dirs: Vec<PathBuf>,
+}
+
+impl Inputs {
+	/// The configuration, the sources, every header recorded in the objects'
+	/// dependency files and the asset/image/audio directories
+	fn from_config(config: &Config) -> Self {
+		let build_path = config.build_path();
+
+		let mut files = vec![config.path.clone()];
+		for src in config.sh_srcs.iter() {
+			files.push(src.clone());
+			if let Ok(target) = build::convert_build_path(&build_path, &src.with_extension("o")) {
+				files.extend(graph::read_depfile(target.with_extension("d")));
+			}
+		}
+
+		let files = files.into_iter()
+			.flat_map(std::path::absolute)
+			.collect();
+		let dirs = [&config.dir_asset, &config.dir_image, &config.dir_audio].into_iter()
+			.flat_map(std::path::absolute)
+			.collect();
+
+		Self { files, dirs }
+	}
+
+	fn contains(&self, path: &Path) -> bool {
+		self.files.iter().any(|file| file == path)
+		|| self.dirs.iter().any(|dir| path.starts_with(dir))
+	}
+
+	/// Watch the parent directory of each file rather than the file itself,
+	/// since editors often save by replacing the file.
+	fn watch<W: Watcher>(&self, watcher: &mut W) {
+		let mut parents: Vec<&Path> = self.files.iter()
+			.flat_map(|file| file.parent())
+			.collect();
+		parents.sort_unstable();
+		parents.dedup();
+
+		for dir in parents {
+			if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+				warn!("unable to watch '{}': {e}", dir.display());
+			}
+		}
+		for dir in self.dirs.iter().filter(|dir| dir.exists()) {
+			if let Err(e) = watcher.watch(dir, RecursiveMode::Recursive) {
+				warn!("unable to watch '{}': {e}", dir.display());
+			}
+		}
+	}
+}
+
+/// Rebuild whenever an input changes, optionally relaunching `emulator` after
+/// every successful build. Only stale steps are rerun by the build itself.
+pub fn watch(
+	config_path: &Path,
+	options: graph::Options,
+	reporter: &dyn graph::Reporter,
+	emulator: Option<&Emulator>,
+	emulator_args: &[String],
+) -> std::io::Result<()> {
+	let (tx, rx) = mpsc::channel();
+	let mut inputs = Inputs::default();
+	let mut running: Option<duct::Handle> = None;
+
+	loop {
+		// Configuration and build errors are reported without stopping the watch
+		let config = Config::load(config_path);
+		let debounce = config.as_ref()
+			.ok()
+			.and_then(|config| config.table.get("watch"))
+			.and_then(|watch| watch.get("debounce"))
+			.and_then(Value::as_integer)
+			.map(|ms| Duration::from_millis(ms as u64))
+			.unwrap_or(Duration::from_millis(200));
+
+		match config {
+			Ok(config) => {
+				inputs = Inputs::from_config(&config);
+				match build::build(&config, options, reporter) {
+					Ok(outputs) => {
+						info!("build finished");
+						// Headers may have changed with the build
+						inputs = Inputs::from_config(&config);
+
+						if let Some(emulator) = emulator {
+							if let Some(handle) = running.take() {
+								handle.kill()?;
+							}
+							trace!("launching {}", emulator.name);
+							running = Some(emulator.start(&outputs.artifacts(), emulator_args)?);
+						}
+					}
+					Err(e) => error!("build failed: {e}"),
+				}
+			}
+			Err(e) => {
+				error!("{e}");
+				inputs.files.push(std::path::absolute(config_path)?);
+			}
+		}
+
+		let mut watcher = notify::recommended_watcher(tx.clone())
+			.map_err(std::io::Error::other)?;
+		inputs.watch(&mut watcher);
+
+		// Ignore anything the build wrote into the watched directories
+		std::thread::sleep(debounce);
+		while rx.try_recv().is_ok() {}
+
+		info!("watching for changes");
+		wait_for_change(&rx, &inputs, debounce)?;
+	}
+}
+
+/// Block until an input changes, then until no further changes arrive for
+/// the `debounce` period.
+fn wait_for_change(
+	rx: &mpsc::Receiver<notify::Result<notify::Event>>,
+	inputs: &Inputs,
+	debounce: Duration,
+) -> std::io::Result<()> {
+	let is_relevant = |event: notify::Result<notify::Event>| match event {
+		Ok(event) => {
+			let changed: Vec<&PathBuf> = event.paths.iter()
+				.filter(|path| inputs.contains(path))
+				.collect();
+			for path in changed.iter() {
+				trace!("changed: {}", path.display());
+			}
+			!changed.is_empty()
+		}
+		Err(e) => {
+			warn!("watch error: {e}");
+			false
+		}
+	};
+
+	loop {
+		let event = rx.recv().map_err(std::io::Error::other)?;
+		if is_relevant(event) {
+			break;
+		}
+	}
+
+	loop {
+		match rx.recv_timeout(debounce) {
+			Ok(event) => { is_relevant(event); }
+			Err(mpsc::RecvTimeoutError::Timeout) => return Ok(()),
+			Err(e) => return Err(std::io::Error::other(e)),
+		}
+	}
+}